@@ -0,0 +1,35 @@
+//! Times the day 7 phase search on a bank of nine amplifiers with an
+//! increasing number of threads.
+//!
+//!     cargo run --release --example amplifier_scaling
+
+use std::process;
+use std::thread;
+use std::time::Instant;
+
+use advent_of_code_2019::day7::{rank_settings_with_threads, Amplifiers, Mode};
+use advent_of_code_2019::intcode;
+
+fn main() {
+    let program: Vec<i64> = intcode::read_input("input/day7.txt");
+    let amplifiers = match Amplifiers::with_phases(Mode::Serial, 9, &[0, 1, 2, 3, 4, 0, 1, 2, 3], 0) {
+        Ok(amplifiers) => amplifiers,
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+    };
+    let cores = thread::available_parallelism().map_or(1, |cores| cores.get());
+    println!("{} configurations, {} cores", amplifiers.configurations().len(), cores);
+
+    let mut baseline = None;
+    let mut threads = 1;
+    while threads <= cores.max(4) {
+        let start = Instant::now();
+        let best = rank_settings_with_threads(&program, &amplifiers, threads).swap_remove(0);
+        let elapsed = start.elapsed().as_secs_f64();
+        let speedup = *baseline.get_or_insert(elapsed) / elapsed;
+        println!("{:>3} threads: {:>8.3}s  x{:.2}  best {:?} -> {}", threads, elapsed, speedup, best.phases, best.output);
+        threads *= 2;
+    }
+}
@@ -0,0 +1,230 @@
+use std::env;
+use std::io::{self, Write};
+use std::process;
+
+use advent_of_code_2019::intcode::{self, ascii, disasm, Machine, State};
+
+const USAGE: &str = "\
+usage: intcode <command> <program> [--input 1,2,3] [--budget N]
+
+commands:
+    run       run the program, printing every output on its own line
+    trace     run the program, printing every executed instruction
+    disasm    disassemble the program
+    ascii     run the program interactively, talking ASCII on stdin and stdout
+
+exit codes:
+    0         the program halted
+    1         the program failed
+    2         the instruction budget was exhausted
+    3         the program needs more input
+    64        invalid arguments";
+
+const HALTED: i32 = 0;
+const FAILED: i32 = 1;
+const OUT_OF_BUDGET: i32 = 2;
+const NEEDS_INPUT: i32 = 3;
+const USAGE_ERROR: i32 = 64;
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    command: String,
+    program: Vec<i64>,
+    inputs: Vec<i64>,
+    budget: Option<u64>,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let (command, filename) = match args {
+        [command, filename, ..] => (command.clone(), filename),
+        _ => return Err("expected a command and a program".to_owned()),
+    };
+    let program = intcode::read_program(filename)?;
+    let mut options = Options { command, program, inputs: Vec::new(), budget: None };
+
+    let mut rest = args[2..].iter();
+    while let Some(option) = rest.next() {
+        let value = rest.next().ok_or_else(|| format!("missing value for {}", option))?;
+        match option.as_str() {
+            "--input" => options.inputs = intcode::parse_numbers(value)?,
+            "--budget" => options.budget = Some(value.parse().map_err(|_| format!("invalid budget {:?}", value))?),
+            _ => return Err(format!("unknown option {}", option)),
+        }
+    }
+    Ok(options)
+}
+
+fn exit_code(error: &intcode::Error) -> i32 {
+    match error {
+        intcode::Error::InstructionLimit { .. } => OUT_OF_BUDGET,
+        intcode::Error::EndOfInput { .. } => NEEDS_INPUT,
+        _ => FAILED,
+    }
+}
+
+fn fail(error: &intcode::Error) -> i32 {
+    eprintln!("error: {}", error);
+    exit_code(error)
+}
+
+fn machine(options: &Options) -> Machine {
+    let mut machine = Machine::new(&options.program);
+    if let Some(budget) = options.budget {
+        machine.set_instruction_limit(budget);
+    }
+    machine
+}
+
+/// Writes every output as soon as the program produces it, so that the
+/// outputs before a failure are shown too.
+fn run<W: Write>(options: &Options, mut output: W) -> i32 {
+    let mut machine = machine(options);
+    for input in &options.inputs {
+        machine.push_input(*input);
+    }
+    loop {
+        let state = match machine.run() {
+            Ok(state) => state,
+            Err(error) => return fail(&error),
+        };
+        match state {
+            State::Output(value) => {
+                if let Err(error) = writeln!(output, "{}", value) {
+                    eprintln!("error: {}", error);
+                    return FAILED;
+                }
+            }
+            State::AwaitingInput => return fail(&intcode::Error::EndOfInput { ip: machine.ip() }),
+            State::Halted => return HALTED,
+        }
+    }
+}
+
+fn trace(options: &Options) -> i32 {
+    let mut machine = machine(options);
+    for input in &options.inputs {
+        machine.push_input(*input);
+    }
+    loop {
+        let ip = machine.ip();
+        if let Some(instruction) = disasm::decode(machine.memory(), ip) {
+            println!("{:>6}: {}", ip, instruction);
+        }
+        match machine.step() {
+            Ok(Some(State::Output(value))) => println!("        output {}", value),
+            Ok(Some(State::Halted)) => return HALTED,
+            Ok(Some(State::AwaitingInput)) => return fail(&intcode::Error::EndOfInput { ip }),
+            Ok(None) => (),
+            Err(error) => return fail(&error),
+        }
+    }
+}
+
+fn disassemble(options: &Options) -> i32 {
+    for line in disasm::disassemble(&options.program) {
+        println!("{}", line);
+    }
+    HALTED
+}
+
+fn interactive(options: &Options) -> i32 {
+    let mut machine = machine(options);
+    for input in &options.inputs {
+        machine.push_input(*input);
+    }
+    match ascii::terminal(&mut machine) {
+        Ok(State::Halted) => HALTED,
+        Ok(_) => {
+            eprintln!("error: input ended before the program halted");
+            NEEDS_INPUT
+        }
+        Err(ascii::Error::Machine(error)) => fail(&error),
+        Err(error) => {
+            eprintln!("error: {}", error);
+            FAILED
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match parse_options(&args) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("error: {}\n{}", error, USAGE);
+            process::exit(USAGE_ERROR);
+        }
+    };
+    let code = match options.command.as_str() {
+        "run" => run(&options, io::stdout().lock()),
+        "trace" => trace(&options),
+        "disasm" => disassemble(&options),
+        "ascii" => interactive(&options),
+        command => {
+            eprintln!("error: unknown command {}\n{}", command, USAGE);
+            USAGE_ERROR
+        }
+    };
+    process::exit(code);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    /// Options for `program`, stored in a temporary file.
+    fn options(name: &str, program: &str, rest: &[&str]) -> Result<Options, String> {
+        let path = env::temp_dir().join(format!("intcode-cli-{}-{}.txt", name, process::id()));
+        let filename = path.to_str().unwrap();
+        fs::write(filename, program).unwrap();
+        let mut all = args(&["run", filename]);
+        all.extend(args(rest));
+        let options = parse_options(&all);
+        fs::remove_file(filename).unwrap();
+        options
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let parsed = options("parse", "104,7,99\n", &["--input", "1, 2", "--budget", "10"]).unwrap();
+        assert_eq!(parsed, Options {
+            command: "run".to_owned(),
+            program: vec![104, 7, 99],
+            inputs: vec![1, 2],
+            budget: Some(10),
+        });
+        assert_eq!(options("missing", "99", &["--input"]).err(), Some("missing value for --input".to_owned()));
+        assert_eq!(options("unknown", "99", &["--verbose", "1"]).err(), Some("unknown option --verbose".to_owned()));
+        assert_eq!(options("budget", "99", &["--budget", "-1"]).err(), Some("invalid budget \"-1\"".to_owned()));
+        assert!(options("program", "1,x", &[]).unwrap_err().ends_with("invalid number \"x\""));
+        assert_eq!(parse_options(&args(&["run"])).err(), Some("expected a command and a program".to_owned()));
+    }
+
+    #[test]
+    fn test_exit_code() {
+        assert_eq!(exit_code(&intcode::Error::InstructionLimit { ip: 0 }), OUT_OF_BUDGET);
+        assert_eq!(exit_code(&intcode::Error::EndOfInput { ip: 0 }), NEEDS_INPUT);
+        assert_eq!(exit_code(&intcode::Error::InvalidInstruction { ip: 0, instruction: 42 }), FAILED);
+    }
+
+    #[test]
+    fn test_run() {
+        let run_program = |program: Vec<i64>, inputs: Vec<i64>, budget: Option<u64>| {
+            let options = Options { command: "run".to_owned(), program, inputs, budget };
+            let mut output = Vec::new();
+            let code = run(&options, &mut output);
+            (code, String::from_utf8(output).unwrap())
+        };
+        assert_eq!(run_program(vec![3, 0, 4, 0, 99], vec![5], None), (HALTED, "5\n".to_owned()));
+        // The outputs before the program gets stuck are still printed.
+        assert_eq!(run_program(vec![104, 7, 104, 8, 3, 0, 99], vec![], None), (NEEDS_INPUT, "7\n8\n".to_owned()));
+        assert_eq!(run_program(vec![104, 7, 42], vec![], None), (FAILED, "7\n".to_owned()));
+        assert_eq!(run_program(vec![104, 7, 1105, 1, 0], vec![], Some(5)), (OUT_OF_BUDGET, "7\n7\n7\n".to_owned()));
+    }
+}
@@ -1,33 +1,43 @@
-use std::fs;
-
 use crate::intcode;
+use crate::intcode::search::{Extract, Patch, Search};
+use crate::intcode::symbolic;
 
-pub fn solve() -> Option<i32> {
-    let filename = "input/day2.txt";
-    let contents = fs::read_to_string(filename).expect("Something went wrong reading the file");
-    let original_input: Vec<i32> = contents
-        .split(',')
-        .map(|number| number.parse::<i32>().unwrap())
-        .collect();
+const TARGET: i64 = 19_690_720;
+/// Instructions a run of the gravity assist program may execute.
+const BUDGET: u64 = 10_000;
 
-    let mut answer = None;
-    for noun in 0..100 {
-        for verb in 0..100 {
-            let result = try_input(&mut original_input.clone(), noun, verb);
-            if result == 19_690_720 {
-                answer = Some(100 * noun + verb);
-                break;
-            }
-        }
-    }
-    answer
+pub fn solve() -> (i64, Option<i64>) {
+    let filename = "input/day2.txt";
+    let program: Vec<i64> = intcode::read_input(filename);
+    let search = noun_and_verb(&program);
+    let alarm = search.evaluate(&[12, 2]).expect("1202 program alarm failed");
+    let answer = symbolic::solve_memory(&program, &[(1, 0..100), (2, 0..100)], 0, TARGET, BUDGET)
+        .expect("Noun and verb are outside the program")
+        .map(|assignment| 100 * assignment[0] + assignment[1]);
+    (alarm, answer)
 }
 
-fn try_input(data: &mut [i32], noun: i32, verb: i32) -> i32 {
-    data[1] = noun;
-    data[2] = verb;
-    let output = intcode::program(data, 0);
-    data[0]
+/// Patches the noun into address 1 and the verb into address 2, the result
+/// is left in address 0.
+fn noun_and_verb(program: &[i64]) -> Search {
+    let patches = vec![Patch { address: 1, values: 0..100 }, Patch { address: 2, values: 0..100 }];
+    Search::new(program, patches, Extract::Memory(0), BUDGET).expect("Noun and verb are outside the program")
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
 
+    #[test]
+    fn test_examples() {
+        let search = noun_and_verb(&[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
+        assert_eq!(search.evaluate(&[9, 10]), Some(3500));
+        let search = noun_and_verb(&[1, 1, 1, 4, 99, 5, 6, 0, 99]);
+        assert_eq!(search.evaluate(&[1, 1]), Some(30));
+    }
+
+    #[test]
+    fn test_solve() {
+        assert_eq!(solve(), (2_842_648, Some(9074)));
+    }
+}
@@ -1,10 +1,125 @@
+use std::fmt;
+
 use crate::intcode;
+use crate::intcode::{disasm, Machine, State};
 
-pub fn solve() -> i64 {
+pub fn solve() -> (i64, i64) {
     let filename = "input/day5.txt";
-    let mut original_input: Vec<i64> = intcode::read_input(filename);
-    let output = intcode::program(&mut original_input, ||5, None);
-    1
+    let program: Vec<i64> = intcode::read_input(filename);
+    let diagnose = |system_id| run_diagnostic(&program, system_id).unwrap_or_else(|error| panic!("{}", error));
+    (diagnose(1), diagnose(5))
 }
 
+/// A diagnostic code of the TEST program.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Output {
+    pub code: i64,
+    /// Address of the instruction which sent the code.
+    pub ip: usize,
+    /// That instruction as it was in memory when it ran, the program may
+    /// have changed it.
+    pub instruction: Option<disasm::Instruction>,
+}
 
+#[derive(Debug, PartialEq, Clone)]
+pub enum Error {
+    Machine(intcode::Error),
+    NoDiagnosticCode,
+    /// The test sent a non-zero code before the final diagnostic code, tests
+    /// are counted from 1.
+    TestFailed { test: usize, code: i64, ip: usize, instruction: Option<disasm::Instruction> },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Machine(error) => write!(f, "Diagnostic program failed: {}", error),
+            Error::NoDiagnosticCode => write!(f, "Diagnostic program halted without a diagnostic code"),
+            Error::TestFailed { test, code, ip, instruction } => {
+                write!(f, "Test {} failed with code {} sent at {}", test, code, ip)?;
+                match instruction {
+                    Some(instruction) => write!(f, ": {}", instruction),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+impl From<intcode::Error> for Error {
+    fn from(error: intcode::Error) -> Error {
+        Error::Machine(error)
+    }
+}
+
+/// Runs the program for `system_id` and collects every code it sends along
+/// with the instruction which sent it, decoded from memory at that moment.
+fn collect_outputs(program: &[i64], system_id: i64) -> Result<Vec<Output>, intcode::Error> {
+    let mut machine = Machine::new(program);
+    machine.push_input(system_id);
+    let mut outputs = Vec::new();
+    loop {
+        let ip = machine.ip();
+        match machine.step()? {
+            Some(State::Output(code)) => {
+                let instruction = disasm::decode(machine.memory(), ip);
+                outputs.push(Output { code, ip, instruction })
+            }
+            Some(State::Halted) => return Ok(outputs),
+            Some(State::AwaitingInput) => return Err(intcode::Error::EndOfInput { ip }),
+            None => (),
+        }
+    }
+}
+
+/// Returns the final diagnostic code once every test before it passed with
+/// code zero.
+pub fn run_diagnostic(program: &[i64], system_id: i64) -> Result<i64, Error> {
+    let outputs = collect_outputs(program, system_id)?;
+    let (last, tests) = outputs.split_last().ok_or(Error::NoDiagnosticCode)?;
+    if let Some((test, failed)) = tests.iter().enumerate().find(|(_, output)| output.code != 0) {
+        return Err(Error::TestFailed {
+            test: test + 1,
+            code: failed.code,
+            ip: failed.ip,
+            instruction: failed.instruction.clone(),
+        });
+    }
+    Ok(last.code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_diagnostic() {
+        let program = intcode![out #0; out #0; out #42; halt];
+        assert_eq!(run_diagnostic(&program, 1), Ok(42));
+        assert_eq!(run_diagnostic(&[99], 1), Err(Error::NoDiagnosticCode));
+        assert_eq!(run_diagnostic(&[3, 0, 3, 0, 99], 1), Err(Error::Machine(intcode::Error::EndOfInput { ip: 2 })));
+    }
+
+    #[test]
+    fn test_failed_test() {
+        let program = intcode![in id; out #0; out id; out #42; halt; id: 0];
+        let error = run_diagnostic(&program, 7).unwrap_err();
+        assert_eq!(error.to_string(), "Test 2 failed with code 7 sent at 4: out [9]");
+    }
+
+    #[test]
+    fn test_modified_instruction() {
+        // Patches the operand of the first output before it runs.
+        let program = vec![1101, 0, 9, 5, 104, 0, 104, 42, 99];
+        let outputs = collect_outputs(&program, 1).unwrap();
+        let instructions: Vec<String> = outputs.iter().map(|output| output.instruction.as_ref().unwrap().to_string()).collect();
+        assert_eq!(instructions, vec!["out #9", "out #42"]);
+        let error = run_diagnostic(&program, 1).unwrap_err();
+        assert_eq!(error.to_string(), "Test 1 failed with code 9 sent at 4: out #9");
+    }
+
+    #[test]
+    fn test_solve() {
+        assert_eq!(solve(), (6_761_139, 9_217_546));
+    }
+}
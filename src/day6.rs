@@ -27,12 +27,9 @@ fn count_orbits(lines: &Vec<String>) -> usize {
         let current = queue.remove(0);
         match current {
             Some(current_name) => {
-                match map.get_mut(&current_name) {
-                    Some(children) => {
-                        count += children.len() * distance_to_root;
-                        children.iter().for_each(|s| queue.push(Some(s.clone())));
-                    }
-                    None => (),
+                if let Some(children) = map.get_mut(&current_name) {
+                    count += children.len() * distance_to_root;
+                    children.iter().for_each(|s| queue.push(Some(s.clone())));
                 }
             },
             None => {
@@ -72,21 +69,21 @@ mod tests {
 
     #[test]
     fn test_two() {
-        let lines = vec!["COM)B", "COM)C"].iter().map(|s| String::from(*s)).collect();
+        let lines = ["COM)B", "COM)C"].iter().map(|s| String::from(*s)).collect();
 
         assert_eq!(count_orbits(&lines), 2);
     }
 
     #[test]
     fn test_tree() {
-        let lines = vec!["COM)B", "B)C"].iter().map(|s| String::from(*s)).collect();
+        let lines = ["COM)B", "B)C"].iter().map(|s| String::from(*s)).collect();
 
         assert_eq!(count_orbits(&lines), 3);
     }
 
     #[test]
     fn test_four() {
-        let lines = vec![
+        let lines = [
             "COM)B",
             "B)C",
             "COM)D"
@@ -97,7 +94,7 @@ mod tests {
 
     #[test]
     fn test_seven() {
-        let lines = vec![
+        let lines = [
             "COM)B",
             "B)C",
             "B)D"
@@ -108,7 +105,7 @@ mod tests {
 
     #[test]
     fn test_example() {
-        let lines = vec!["COM)B",
+        let lines = ["COM)B",
                          "B)C",
                          "C)D",
                          "D)E",
@@ -1,64 +1,349 @@
-use permutohedron::Heap;
+use std::fmt;
+use std::sync::Arc;
 
 use crate::intcode;
+use crate::intcode::batch::Batch;
+use crate::intcode::network::{Node, Source, Topology};
+use crate::intcode::Machine;
 
-pub fn solve() -> i64 {
+pub fn solve() -> (i64, i64) {
     let filename = "input/day7.txt";
     let program: Vec<i64> = intcode::read_input(filename);
-    find_max_output(&program)
+    let serial = Amplifiers::new(Mode::Serial);
+    let feedback = Amplifiers::new(Mode::Feedback);
+    (find_max_output(&program, &serial).output, find_max_output(&program, &feedback).output)
 }
 
-fn find_max_output(program: &[i64]) -> i64 {
-    let mut max_output = 0;
-    let mut configuration = [0, 1, 2, 3, 4];
-    let mut heap = Heap::new(&mut configuration);
-    while let Some(possible_configuration) = heap.next_permutation() {
-        let output = get_thrusters_output(program, &possible_configuration[..]);
-        if output > max_output {
-            max_output = output
+/// How the amplifiers are wired: in a chain where every amplifier runs once,
+/// or in a loop where the last amplifier feeds the first until they halt.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Mode {
+    Serial,
+    Feedback,
+}
+
+impl Mode {
+    fn phases(self) -> Vec<i64> {
+        match self {
+            Mode::Serial => vec![0, 1, 2, 3, 4],
+            Mode::Feedback => vec![5, 6, 7, 8, 9],
         }
     }
+}
 
-    max_output
+/// A bank of amplifiers: every amplifier takes one of `phases`, a value
+/// listed twice may be used by two amplifiers. The first amplifier receives
+/// `signal` after its phase.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Amplifiers {
+    pub mode: Mode,
+    pub count: usize,
+    pub phases: Vec<i64>,
+    pub signal: i64,
 }
 
+impl Amplifiers {
+    /// The five amplifiers of the puzzle.
+    pub fn new(mode: Mode) -> Amplifiers {
+        Amplifiers { mode, count: 5, phases: mode.phases(), signal: 0 }
+    }
 
-fn get_thrusters_output(program: &[i64], configuration: &[i64]) -> i64 {
-    let mut last_value: i64 = 0;
-    let mut programs: Vec<Vec<i64>> = Vec::new();
-    let mut inputs: Vec<i64> = Vec::new();
-    let mut positions: Vec<Option<usize>> = Vec::new();
-    for _ in 0..configuration.len() {
-        programs.push(program.to_vec());
-        inputs.push(0);
-        positions.push(None);
+    /// Fails unless there is at least one amplifier and a phase for each.
+    pub fn with_phases(mode: Mode, count: usize, phases: &[i64], signal: i64) -> Result<Amplifiers, String> {
+        if count == 0 || count > phases.len() {
+            return Err(format!("{} amplifiers need as many phases, got {}", count, phases.len()));
+        }
+        let mut phases = phases.to_vec();
+        phases.sort_unstable();
+        Ok(Amplifiers { mode, count, phases, signal })
     }
-    let mut index = 0;
-    let mut loop_count = 0;
-    loop {
-        if index == 0 {
-            println!("Loop {:?}", loop_count);
-            loop_count += 1;
+
+    /// Every distinct assignment of phases to the amplifiers, in
+    /// lexicographic order.
+    pub fn configurations(&self) -> Vec<Vec<i64>> {
+        let mut configurations = Vec::new();
+        let mut used = vec![false; self.phases.len()];
+        let mut prefix = Vec::with_capacity(self.count);
+        self.walk(&mut prefix, &mut used, (), &mut |_, _, _| (), &mut |configuration, _| {
+            configurations.push(configuration.to_vec())
+        });
+        configurations
+    }
+
+    /// Visits every configuration starting with `prefix`. `step` turns the
+    /// value of a prefix into the value of the prefix extended by one phase,
+    /// given the position and phase of the added amplifier, so configurations
+    /// sharing a prefix share its work.
+    fn walk<S>(
+        &self,
+        prefix: &mut Vec<i64>,
+        used: &mut [bool],
+        value: S,
+        step: &mut dyn FnMut(&S, usize, i64) -> S,
+        leaf: &mut dyn FnMut(&[i64], S),
+    ) {
+        if prefix.len() == self.count {
+            leaf(prefix, value);
+            return;
+        }
+        for index in 0..self.phases.len() {
+            // Equal phases are interchangeable, only the first unused one of
+            // a run is tried so every configuration shows up once.
+            let duplicate = index > 0 && self.phases[index - 1] == self.phases[index] && !used[index - 1];
+            if used[index] || duplicate {
+                continue;
+            }
+            used[index] = true;
+            let next = step(&value, prefix.len(), self.phases[index]);
+            prefix.push(self.phases[index]);
+            self.walk(prefix, used, next, step, leaf);
+            prefix.pop();
+            used[index] = false;
+        }
+    }
+
+    /// Every distinct serial prefix of up to `length` amplifiers with the
+    /// signal it sends, along with the number of amplifiers run.
+    fn serial_prefixes(&self, machine: &Machine, length: usize) -> (Vec<(Vec<i64>, i64)>, usize) {
+        let shorter = Amplifiers { count: length.min(self.count), ..self.clone() };
+        let mut prefixes = Vec::new();
+        let mut used = vec![false; self.phases.len()];
+        let mut runs = 0;
+        shorter.walk(
+            &mut Vec::new(),
+            &mut used,
+            self.signal,
+            &mut |signal, position, phase| {
+                runs += 1;
+                amplify(machine, position, phase, *signal)
+            },
+            &mut |prefix, signal| prefixes.push((prefix.to_vec(), signal)),
+        );
+        (prefixes, runs)
+    }
+
+    /// Every serial configuration starting with `prefix`, which sends
+    /// `signal`, along with the number of amplifiers run. The signal after
+    /// each longer prefix is computed once and reused by every configuration
+    /// extending it.
+    fn serial_settings(&self, machine: &Machine, prefix: &[i64], signal: i64) -> (Vec<Setting>, usize) {
+        let mut settings = Vec::new();
+        let mut used = vec![false; self.phases.len()];
+        for phase in prefix {
+            let index = (0..self.phases.len()).find(|index| !used[*index] && self.phases[*index] == *phase).unwrap();
+            used[index] = true;
+        }
+        let mut runs = 0;
+        self.walk(
+            &mut prefix.to_vec(),
+            &mut used,
+            signal,
+            &mut |signal, position, phase| {
+                runs += 1;
+                amplify(machine, position, phase, *signal)
+            },
+            &mut |configuration, output| settings.push(Setting { phases: configuration.to_vec(), output }),
+        );
+        (settings, runs)
+    }
+
+    /// The signal the last amplifier sends to the thrusters.
+    pub fn output(&self, program: &[i64], configuration: &[i64]) -> i64 {
+        self.run(&Machine::new(program), configuration)
+    }
+
+    /// Like `output`, also recording every signal passed between amplifiers.
+    pub fn trace(&self, program: &[i64], configuration: &[i64]) -> (i64, Log) {
+        let mut log = Log { signal: self.signal, amplifiers: configuration.len(), signals: Vec::new() };
+        let output = self.run_logged(&Machine::new(program), configuration, Some(&mut log));
+        (output, log)
+    }
+
+    /// The amplifiers as a machine network, every node running `program`.
+    pub fn topology(&self, program: &[i64], configuration: &[i64]) -> Result<Topology, String> {
+        if configuration.is_empty() {
+            return Err("no amplifiers to connect".to_owned());
+        }
+        let program = Source::Code(Arc::new(program.to_vec()));
+        let mut nodes: Vec<Node> = configuration
+            .iter()
+            .enumerate()
+            .map(|(index, phase)| Node { name: amplifier_name(index), program: program.clone(), inputs: vec![*phase] })
+            .collect();
+        nodes[0].inputs.push(self.signal);
+        let mut edges: Vec<(usize, usize)> = (1..nodes.len()).map(|index| (index - 1, index)).collect();
+        if self.mode == Mode::Feedback {
+            edges.push((nodes.len() - 1, 0));
+        }
+        Ok(Topology { output: nodes.len() - 1, nodes, edges })
+    }
+
+    /// Like `output`, every amplifier is a clone of `machine`.
+    fn run(&self, machine: &Machine, configuration: &[i64]) -> i64 {
+        self.run_logged(machine, configuration, None)
+    }
+
+    fn run_logged(&self, machine: &Machine, configuration: &[i64], log: Option<&mut Log>) -> i64 {
+        match self.mode {
+            Mode::Serial => get_serial_output(machine, configuration, self.signal, log),
+            Mode::Feedback => get_feedback_output(machine, configuration, self.signal, log),
         }
-        let mut input_call_count = 0;
-        let input = || {
-            if input_call_count == 0 {
-                input_call_count += 1;
-                return configuration[index];
+    }
+}
+
+/// A phase configuration together with the signal it sends to the thrusters.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Setting {
+    pub phases: Vec<i64>,
+    pub output: i64,
+}
+
+/// Every configuration of `amplifiers`, strongest signal first. Ties are
+/// ordered by phases so the table is the same on every run.
+pub fn rank_settings(program: &[i64], amplifiers: &Amplifiers) -> Vec<Setting> {
+    rank_with_batch(&Batch::new(program), amplifiers)
+}
+
+/// `rank_settings` with the configurations spread over `threads` threads.
+pub fn rank_settings_with_threads(program: &[i64], amplifiers: &Amplifiers, threads: usize) -> Vec<Setting> {
+    rank_with_batch(&Batch::with_threads(program, threads), amplifiers)
+}
+
+/// Phases shared by the serial configurations of one task.
+const SERIAL_SPLIT: usize = 2;
+
+/// Serial configurations are split by their first two phases and searched
+/// prefix by prefix, feedback loops have to run every configuration in full.
+fn rank_with_batch(batch: &Batch, amplifiers: &Amplifiers) -> Vec<Setting> {
+    let mut settings: Vec<Setting> = match amplifiers.mode {
+        Mode::Serial => {
+            let (prefixes, _) = amplifiers.serial_prefixes(&batch.machine(), SERIAL_SPLIT);
+            batch
+                .map(prefixes, |machine, (prefix, signal)| amplifiers.serial_settings(&machine, prefix, *signal).0)
+                .into_iter()
+                .flatten()
+                .collect()
+        }
+        Mode::Feedback => batch.map(amplifiers.configurations(), |machine, phases| Setting {
+            phases: phases.clone(),
+            output: amplifiers.run(&machine, phases),
+        }),
+    };
+    settings.sort_by(|a, b| b.output.cmp(&a.output).then_with(|| a.phases.cmp(&b.phases)));
+    settings
+}
+
+pub fn find_max_output(program: &[i64], amplifiers: &Amplifiers) -> Setting {
+    rank_settings(program, amplifiers).swap_remove(0)
+}
+
+
+/// A signal sent by `amplifier` during a round of the feedback loop, the
+/// serial chain only has round 0.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Signal {
+    pub round: usize,
+    pub amplifier: usize,
+    pub value: i64,
+}
+
+/// Every signal passed between the amplifiers, in the order they were sent.
+/// Displays as a table with a row per round:
+///
+/// ```text
+/// round  input      A      B
+///     0      0      1      3
+///     1      3      7     15
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Log {
+    /// The signal the first amplifier received first.
+    pub signal: i64,
+    pub amplifiers: usize,
+    pub signals: Vec<Signal>,
+}
+
+fn amplifier_name(index: usize) -> String {
+    if index < 26 {
+        ((b'A' + index as u8) as char).to_string()
+    } else {
+        format!("#{}", index)
+    }
+}
+
+impl fmt::Display for Log {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = self
+            .signals
+            .iter()
+            .map(|signal| signal.value.to_string().len())
+            .chain(vec![self.signal.to_string().len(), 5])
+            .max()
+            .unwrap();
+        write!(f, "round  {:>width$}", "input", width = width)?;
+        for index in 0..self.amplifiers {
+            write!(f, " {:>width$}", amplifier_name(index), width = width)?;
+        }
+        let mut input = self.signal;
+        for (position, signal) in self.signals.iter().enumerate() {
+            if position == 0 || signal.round != self.signals[position - 1].round {
+                write!(f, "\n{:>5}  {:>width$}", signal.round, input, width = width)?;
             }
-            inputs[index]
-        };
-        let current_program = &mut programs[index];
-        let current_position = positions[index];
-        let output = intcode::program(current_program, input, current_position);
-        let next_index = (index + 1) % 5;
+            write!(f, " {:>width$}", signal.value, width = width)?;
+            input = signal.value;
+        }
+        writeln!(f)
+    }
+}
+
+/// Runs a single amplifier of a serial chain.
+fn amplify(template: &Machine, index: usize, phase: i64, signal: i64) -> i64 {
+    let mut machine = template.clone();
+    machine.push_input(phase);
+    machine.push_input(signal);
+    match machine.run().expect("Amplifier program failed") {
+        intcode::State::Output(value) => value,
+        state => panic!("Amplifier {} stopped without a signal: {:?}", index, state),
+    }
+}
+
+fn get_serial_output(template: &Machine, configuration: &[i64], mut signal: i64, mut log: Option<&mut Log>) -> i64 {
+    for (index, phase) in configuration.iter().enumerate() {
+        signal = amplify(template, index, *phase, signal);
+        if let Some(log) = log.as_mut() {
+            log.signals.push(Signal { round: 0, amplifier: index, value: signal });
+        }
+    }
+    signal
+}
+
+fn get_feedback_output(template: &Machine, configuration: &[i64], signal: i64, mut log: Option<&mut Log>) -> i64 {
+    let mut last_value: i64 = signal;
+    let mut machines: Vec<Machine> = Vec::new();
+    for phase in configuration {
+        let mut machine = template.clone();
+        machine.push_input(*phase);
+        machines.push(machine);
+    }
+    machines[0].push_input(signal);
+    let mut index = 0;
+    let mut round = 0;
+    loop {
+        let output = machines[index].run().expect("Amplifier program failed");
+        let next_index = (index + 1) % machines.len();
         match output {
-            intcode::ProgramResult::Halt => break,
-            intcode::ProgramResult::Output(value, position) => {
+            intcode::State::Halted => break,
+            intcode::State::Output(value) => {
                 last_value = value;
-                inputs[next_index] = value;
-                positions[index] = Some(position);
+                machines[next_index].push_input(value);
+                if let Some(log) = log.as_mut() {
+                    log.signals.push(Signal { round, amplifier: index, value });
+                }
             }
+            intcode::State::AwaitingInput => panic!("Amplifier {} is waiting for a signal", index),
+        }
+        if next_index == 0 {
+            round += 1;
         }
         index = next_index;
     }
@@ -69,37 +354,203 @@ fn get_thrusters_output(program: &[i64], configuration: &[i64]) -> i64 {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::intcode::network;
 
     #[test]
     fn test_thrusters_output_1() {
-        let program = vec![3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1, 28, 1005, 28, 6, 99, 0, 0, 5];
+        let program = intcode![
+            in phase;
+            add phase, #-4 -> phase;
+            next: in signal;
+            mul signal, #2 -> signal;
+            add signal, phase -> signal;
+            out signal;
+            add counter, #-1 -> counter;
+            jt counter, #next;
+            halt;
+            phase: 0;
+            signal: 0;
+            counter: 5
+        ];
         let configuration = vec![9, 8, 7, 6, 5];
-        assert_eq!(get_thrusters_output(&program, &configuration), 139629729)
-    }
-
-//    #[test]
-//    fn test_thrusters_output_2() {
-//        let program = vec![3, 23, 3, 24, 1002, 24, 10, 24, 1002, 23, -1, 23, 101, 5, 23, 23, 1, 24, 23, 23, 4, 23, 99, 0, 0];
-//        let configuration = vec![0, 1, 2, 3, 4];
-//        assert_eq!(get_thrusters_output(&program, &configuration), 54321)
-//    }
-//
-//    #[test]
-//    fn test_thrusters_output_3() {
-//        let program = vec![3, 31, 3, 32, 1002, 32, 10, 32, 1001, 31, -2, 31, 1007, 31, 0, 33, 1002, 33, 7, 33, 1, 33, 31, 31, 1, 32, 31, 31, 4, 31, 99, 0, 0, 0];
-//        let configuration = vec![1, 0, 4, 3, 2];
-//        assert_eq!(get_thrusters_output(&program, &configuration), 65210)
-//    }
-//
-//    #[test]
-//    fn fin_max_output_1() {
-//        let program = vec![3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0];
-//        assert_eq!(find_max_output(&program), 43210)
-//    }
-//
-//    #[test]
-//    fn fin_max_output_2() {
-//        let program = vec![3, 23, 3, 24, 1002, 24, 10, 24, 1002, 23, -1, 23, 101, 5, 23, 23, 1, 24, 23, 23, 4, 23, 99, 0, 0];
-//        assert_eq!(find_max_output(&program), 54321)
-//    }
-}
\ No newline at end of file
+        assert_eq!(Amplifiers::new(Mode::Feedback).output(&program, &configuration), 139629729)
+    }
+
+    #[test]
+    fn test_thrusters_output_2() {
+        let program = vec![3, 23, 3, 24, 1002, 24, 10, 24, 1002, 23, -1, 23, 101, 5, 23, 23, 1, 24, 23, 23, 4, 23, 99, 0, 0];
+        let configuration = vec![0, 1, 2, 3, 4];
+        assert_eq!(Amplifiers::new(Mode::Serial).output(&program, &configuration), 54321)
+    }
+
+    #[test]
+    fn test_thrusters_output_3() {
+        let program = vec![3, 31, 3, 32, 1002, 32, 10, 32, 1001, 31, -2, 31, 1007, 31, 0, 33, 1002, 33, 7, 33, 1, 33, 31, 31, 1, 32, 31, 31, 4, 31, 99, 0, 0, 0];
+        let configuration = vec![1, 0, 4, 3, 2];
+        assert_eq!(Amplifiers::new(Mode::Serial).output(&program, &configuration), 65210)
+    }
+
+    #[test]
+    fn fin_max_output_1() {
+        let program = vec![3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0];
+        let setting = find_max_output(&program, &Amplifiers::new(Mode::Serial));
+        assert_eq!(setting, Setting { phases: vec![4, 3, 2, 1, 0], output: 43210 })
+    }
+
+    #[test]
+    fn fin_max_output_2() {
+        let program = vec![3, 23, 3, 24, 1002, 24, 10, 24, 1002, 23, -1, 23, 101, 5, 23, 23, 1, 24, 23, 23, 4, 23, 99, 0, 0];
+        let setting = find_max_output(&program, &Amplifiers::new(Mode::Serial));
+        assert_eq!(setting, Setting { phases: vec![0, 1, 2, 3, 4], output: 54321 })
+    }
+
+    #[test]
+    fn fin_max_output_feedback() {
+        let program = vec![
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1, 28, 1005, 28, 6, 99, 0, 0, 5,
+        ];
+        let setting = find_max_output(&program, &Amplifiers::new(Mode::Feedback));
+        assert_eq!(setting, Setting { phases: vec![9, 8, 7, 6, 5], output: 139629729 })
+    }
+
+    #[test]
+    fn test_rank_settings() {
+        let program = vec![3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0];
+        let settings = rank_settings(&program, &Amplifiers::new(Mode::Serial));
+        assert_eq!(settings.len(), 120);
+        assert_eq!(settings[1], Setting { phases: vec![4, 3, 2, 0, 1], output: 43201 });
+        assert_eq!(settings.last().unwrap().output, 1234);
+    }
+
+    #[test]
+    fn test_configurations() {
+        let amplifiers = Amplifiers::with_phases(Mode::Serial, 2, &[2, 1, 1], 0).unwrap();
+        assert_eq!(amplifiers.configurations(), vec![vec![1, 1], vec![1, 2], vec![2, 1]]);
+        assert_eq!(Amplifiers::new(Mode::Feedback).configurations().len(), 120);
+        let error = Amplifiers::with_phases(Mode::Serial, 3, &[1, 2], 0);
+        assert_eq!(error, Err("3 amplifiers need as many phases, got 2".to_owned()));
+        assert!(Amplifiers::with_phases(Mode::Serial, 0, &[1, 2], 0).is_err());
+    }
+
+    #[test]
+    fn test_custom_bank() {
+        let program = vec![3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0];
+        let amplifiers = Amplifiers::with_phases(Mode::Serial, 3, &[1, 2, 1], 5).unwrap();
+        assert_eq!(amplifiers.output(&program, &[1, 1, 2]), 5112);
+        let settings = rank_settings(&program, &amplifiers);
+        assert_eq!(settings.len(), 3);
+        assert_eq!(settings[0], Setting { phases: vec![2, 1, 1], output: 5211 });
+    }
+
+    #[test]
+    fn test_serial_duplicates() {
+        let program = vec![3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0];
+        let amplifiers = Amplifiers::with_phases(Mode::Serial, 3, &[1, 2, 1, 2], 0).unwrap();
+        let machine = Machine::new(&program);
+        let (prefixes, _) = amplifiers.serial_prefixes(&machine, SERIAL_SPLIT);
+        let phases: Vec<Vec<i64>> = prefixes.iter().map(|(prefix, _)| prefix.clone()).collect();
+        assert_eq!(phases, vec![vec![1, 1], vec![1, 2], vec![2, 1], vec![2, 2]]);
+        let (settings, _) = amplifiers.serial_settings(&machine, &[1, 1], prefixes[0].1);
+        assert_eq!(settings, vec![Setting { phases: vec![1, 1, 2], output: 112 }]);
+
+        let single = Amplifiers::with_phases(Mode::Serial, 1, &[3, 4], 7).unwrap();
+        assert_eq!(rank_settings(&program, &single)[0], Setting { phases: vec![4], output: 74 });
+    }
+
+    #[test]
+    #[should_panic(expected = "Amplifier 1 stopped without a signal: Halted")]
+    fn test_amplifier_index() {
+        // Amplifiers with phase 1 halt without a signal, the message names the second one.
+        let program = intcode![in p; in s; eq p, #1 -> t; jt t, #done; out s; done: halt; p: 0; s: 0; t: 0];
+        let amplifiers = Amplifiers::with_phases(Mode::Serial, 2, &[0, 1], 0).unwrap();
+        rank_settings(&program, &amplifiers);
+    }
+
+    #[test]
+    fn test_rank_settings_with_threads() {
+        let program = vec![3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0];
+        let amplifiers = Amplifiers::with_phases(Mode::Serial, 4, &[0, 1, 2, 3, 4, 5], 0).unwrap();
+        let single = rank_settings_with_threads(&program, &amplifiers, 1);
+        assert_eq!(single.len(), 360);
+        assert_eq!(rank_settings_with_threads(&program, &amplifiers, 4), single);
+    }
+
+    #[test]
+    fn test_trace() {
+        // Doubles the signal and adds one, twice.
+        let program = intcode![
+            in phase;
+            next: in signal;
+            mul signal, #2 -> signal;
+            add signal, #1 -> signal;
+            out signal;
+            add counter, #-1 -> counter;
+            jt counter, #next;
+            halt;
+            phase: 0;
+            signal: 0;
+            counter: 2
+        ];
+        let amplifiers = Amplifiers::with_phases(Mode::Feedback, 2, &[5, 6], 0).unwrap();
+        let (output, log) = amplifiers.trace(&program, &[5, 6]);
+        assert_eq!(output, 15);
+        assert_eq!(log.signals.len(), 4);
+        assert_eq!(log.signals[2], Signal { round: 1, amplifier: 0, value: 7 });
+        assert_eq!(log.to_string(), "\
+round  input     A     B
+    0      0     1     3
+    1      3     7    15
+");
+
+        let (output, log) = Amplifiers::with_phases(Mode::Serial, 2, &[5, 6], 0).unwrap().trace(&program, &[5, 6]);
+        assert_eq!(output, 3);
+        assert_eq!(log.signals.iter().map(|signal| signal.round).max(), Some(0));
+    }
+
+    #[test]
+    fn test_topology() {
+        let program = intcode::read_input("input/day7.txt");
+        let configuration = [9, 7, 8, 5, 6];
+        let amplifiers = Amplifiers::new(Mode::Feedback);
+        let topology = amplifiers.topology(&program, &configuration).unwrap();
+        let signals = network::run_files(&topology).unwrap();
+        assert_eq!(signals.last(), Some(&amplifiers.output(&program, &configuration)));
+        let loaded = Topology::load("tests/network/day7.txt").unwrap();
+        assert_eq!((&loaded.edges, loaded.output), (&topology.edges, topology.output));
+        assert_eq!(network::run_files(&loaded).unwrap(), signals);
+
+        let amplifiers = Amplifiers::new(Mode::Serial);
+        let topology = amplifiers.topology(&program, &[4, 3, 2, 1, 0]).unwrap();
+        assert_eq!(network::run_files(&topology).unwrap(), vec![amplifiers.output(&program, &[4, 3, 2, 1, 0])]);
+        assert_eq!(amplifiers.topology(&program, &[]), Err("no amplifiers to connect".to_owned()));
+    }
+
+    #[test]
+    fn test_solve() {
+        assert_eq!(solve(), (77_500, 22_476_942));
+    }
+
+    #[test]
+    fn test_serial_prefixes() {
+        let program = intcode::read_input("input/day7.txt");
+        let amplifiers = Amplifiers::new(Mode::Serial);
+        let machine = Machine::new(&program);
+        let (prefixes, mut runs) = amplifiers.serial_prefixes(&machine, SERIAL_SPLIT);
+        assert_eq!(prefixes.len(), 20);
+        assert_eq!(prefixes[1].0, vec![0, 2]);
+        let mut settings = Vec::new();
+        for (prefix, signal) in &prefixes {
+            let (subtree, subtree_runs) = amplifiers.serial_settings(&machine, prefix, *signal);
+            settings.extend(subtree);
+            runs += subtree_runs;
+        }
+        // 5 + 5 * 4 + 5 * 4 * 3 + 120 + 120 distinct prefixes instead of 5 * 120 runs.
+        assert_eq!(runs, 325);
+        let expected: Vec<Setting> = amplifiers
+            .configurations()
+            .into_iter()
+            .map(|phases| Setting { output: amplifiers.output(&program, &phases), phases })
+            .collect();
+        assert_eq!(settings, expected);
+    }
+}
@@ -1,148 +1,561 @@
+use std::collections::VecDeque;
+use std::error;
+use std::fmt;
 use std::fs;
+use std::sync::Arc;
 
-#[derive(Debug, PartialEq)]
+use protection::Access;
+
+pub mod ascii;
+pub mod assembler;
+pub mod async_driver;
+pub mod batch;
+pub mod cfg;
+pub mod conformance;
+pub mod decompile;
+pub mod diff;
+pub mod disasm;
+pub mod extension;
+pub mod fuzz;
+pub mod network;
+pub mod partial;
+pub mod protection;
+pub mod replay;
+pub mod search;
+pub mod symbolic;
+pub mod viewer;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
 enum Mode {
     Position,
     Immediate,
+    Relative,
 }
 
 #[derive(Debug, PartialEq)]
 enum OpCode {
-    Add(Mode, Mode),
-    Multiply(Mode, Mode),
+    Add(Mode, Mode, Mode),
+    Multiply(Mode, Mode, Mode),
     Save(Mode),
     Output(Mode),
     JumpIfTrue(Mode, Mode),
     JumpIfFalse(Mode, Mode),
-    LessThan(Mode, Mode),
-    Equals(Mode, Mode),
+    LessThan(Mode, Mode, Mode),
+    Equals(Mode, Mode, Mode),
+    AdjustRelativeBase(Mode),
     Halt,
 }
 
+impl OpCode {
+    /// Number of memory cells taken by the instruction, including the op code.
+    fn length(&self) -> usize {
+        match self {
+            OpCode::Add(_, _, _) | OpCode::Multiply(_, _, _) | OpCode::LessThan(_, _, _) | OpCode::Equals(_, _, _) => 4,
+            OpCode::JumpIfTrue(_, _) | OpCode::JumpIfFalse(_, _) => 3,
+            OpCode::Save(_) | OpCode::Output(_) | OpCode::AdjustRelativeBase(_) => 2,
+            OpCode::Halt => 1,
+        }
+    }
+
+    /// Parameter modes in the order of the parameters.
+    fn modes(&self) -> Vec<Mode> {
+        match self {
+            OpCode::Add(mode_1, mode_2, mode_3)
+            | OpCode::Multiply(mode_1, mode_2, mode_3)
+            | OpCode::LessThan(mode_1, mode_2, mode_3)
+            | OpCode::Equals(mode_1, mode_2, mode_3) => vec![*mode_1, *mode_2, *mode_3],
+            OpCode::JumpIfTrue(mode_1, mode_2) | OpCode::JumpIfFalse(mode_1, mode_2) => vec![*mode_1, *mode_2],
+            OpCode::Save(mode) | OpCode::Output(mode) | OpCode::AdjustRelativeBase(mode) => vec![*mode],
+            OpCode::Halt => vec![],
+        }
+    }
+}
+
 
-fn instruction_to_op_code(instruction: i64) -> OpCode {
+fn instruction_to_op_code(instruction: i64) -> Option<OpCode> {
     let operation_code = instruction % 100;
     let params = instruction / 100;
     let match_mode = |param: i64| {
         match param {
-            0 => Mode::Position,
-            1 => Mode::Immediate,
-            _ => panic!("An unknown mode for parameter 1: {:?}", params),
+            0 => Some(Mode::Position),
+            1 => Some(Mode::Immediate),
+            2 => Some(Mode::Relative),
+            _ => None,
         }
     };
-    match operation_code {
-        1 | 2 | 5 | 6 | 7 | 8 => {
-            let mode_1 = match_mode(params % 10);
-            let mode_2 = match_mode(params / 10);
+    let op_code = match operation_code {
+        1 | 2 | 7 | 8 => {
+            let mode_1 = match_mode(params % 10)?;
+            let mode_2 = match_mode(params / 10 % 10)?;
+            let mode_3 = match_mode(params / 100)?;
             if operation_code == 1 {
-                OpCode::Add(mode_1, mode_2)
+                OpCode::Add(mode_1, mode_2, mode_3)
             } else if operation_code == 2 {
-                OpCode::Multiply(mode_1, mode_2)
-            } else if operation_code == 5 {
-                OpCode::JumpIfTrue(mode_1, mode_2)
-            } else if operation_code == 6 {
-                OpCode::JumpIfFalse(mode_1, mode_2)
+                OpCode::Multiply(mode_1, mode_2, mode_3)
             } else if operation_code == 7 {
-                OpCode::LessThan(mode_1, mode_2)
+                OpCode::LessThan(mode_1, mode_2, mode_3)
             } else {
-                OpCode::Equals(mode_1, mode_2)
+                OpCode::Equals(mode_1, mode_2, mode_3)
             }
         }
-        3 | 4 => {
-            let mode = match_mode(params);
+        5 | 6 => {
+            let mode_1 = match_mode(params % 10)?;
+            let mode_2 = match_mode(params / 10)?;
+            if operation_code == 5 {
+                OpCode::JumpIfTrue(mode_1, mode_2)
+            } else {
+                OpCode::JumpIfFalse(mode_1, mode_2)
+            }
+        }
+        3 | 4 | 9 => {
+            let mode = match_mode(params)?;
             if operation_code == 3 {
                 OpCode::Save(mode)
-            } else {
+            } else if operation_code == 4 {
                 OpCode::Output(mode)
+            } else {
+                OpCode::AdjustRelativeBase(mode)
             }
         }
         99 => OpCode::Halt,
-        _ => panic!("Unknown operation code {}", operation_code),
+        _ => return None,
+    };
+    Some(op_code)
+}
+
+pub fn read_input(filename: &str) -> Vec<i64> {
+    read_program(filename).unwrap_or_else(|error| panic!("{}", error))
+}
+
+/// Reads a program file such as a puzzle input.
+pub fn read_program(filename: &str) -> Result<Vec<i64>, String> {
+    let contents = fs::read_to_string(filename).map_err(|error| format!("{}: {}", filename, error))?;
+    parse_numbers(&contents).map_err(|error| format!("{}: {}", filename, error))
+}
+
+/// Parses comma separated numbers such as a program or a list of inputs,
+/// ignoring the whitespace around them. Blank text has no numbers.
+pub fn parse_numbers(text: &str) -> Result<Vec<i64>, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(Vec::new());
     }
+    text.split(',')
+        .map(|number| number.trim().parse().map_err(|_| format!("invalid number {:?}", number.trim())))
+        .collect()
 }
 
-fn get_actual_value(data: &[i64], mode: Mode, position: usize) -> i64 {
-    match mode {
-        Mode::Position => data[data[position] as usize].clone(),
-        Mode::Immediate => data[position].clone(),
+#[derive(Debug, PartialEq, Clone)]
+pub enum Error {
+    InvalidInstruction { ip: usize, instruction: i64 },
+    InvalidAddress { ip: usize, address: i64 },
+    EndOfInput { ip: usize },
+    /// A registered op code has failed or returned the wrong number of values.
+    Extension { ip: usize, message: String },
+    Protection { ip: usize, address: usize, access: protection::Access },
+    InstructionLimit { ip: usize },
+    /// An arithmetic result or an address doesn't fit in an `i64`.
+    Overflow { ip: usize },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidInstruction { ip, instruction } => {
+                write!(f, "invalid instruction {} at {}", instruction, ip)
+            }
+            Error::InvalidAddress { ip, address } => {
+                write!(f, "invalid address {} accessed at {}", address, ip)
+            }
+            Error::EndOfInput { ip } => write!(f, "input exhausted at {}", ip),
+            Error::Extension { ip, message } => write!(f, "extension failed at {}: {}", ip, message),
+            Error::Protection { ip, address, access } => {
+                write!(f, "{} access to {} denied at {}", access, address, ip)
+            }
+            Error::InstructionLimit { ip } => write!(f, "instruction limit reached at {}", ip),
+            Error::Overflow { ip } => write!(f, "arithmetic overflow at {}", ip),
+        }
     }
 }
 
-pub fn read_input(filename: &str) -> Vec<i64> {
-    let contents = fs::read_to_string(filename).expect("Something went wrong reading the file");
-    contents
-        .split(',')
-        .map(|number| number.parse::<i64>().unwrap())
-        .collect()
+impl error::Error for Error {}
+
+/// Why `Machine::run` has returned control to the caller.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum State {
+    Output(i64),
+    AwaitingInput,
+    Halted,
 }
 
-pub enum ProgramResult {
-    Halt,
-    Output(i64, usize),   // data, position
+/// Number of cells a machine may address unless told otherwise, 128 MiB of
+/// memory.
+pub const MEMORY_LIMIT: usize = 1 << 24;
+
+/// A resumable intcode machine.
+///
+/// Execution stops on every output and whenever the program needs an input
+/// which has not been pushed yet, so the caller decides how inputs are
+/// produced and where outputs go.
+#[derive(Clone)]
+pub struct Machine {
+    /// Shared with the clones of the machine until one of them writes.
+    memory: Arc<Vec<i64>>,
+    ip: usize,
+    relative_base: i64,
+    inputs: VecDeque<i64>,
+    halted: bool,
+    instruction_count: u64,
+    instruction_limit: Option<u64>,
+    memory_limit: usize,
+    /// For every cell, the ip of the instruction which wrote it last.
+    writers: Option<Vec<Option<usize>>>,
+    extensions: Option<Arc<extension::Registry>>,
+    protection: Option<protection::Policy>,
 }
 
-pub fn program<F>(data: &mut [i64], mut input: F, position: Option<usize>) -> ProgramResult where
-    F: FnMut() -> i64 {
-    let mut position = position.unwrap_or(0);
-    loop {
-        let operation_code = instruction_to_op_code(data[position]);
+impl Machine {
+    pub fn new(program: &[i64]) -> Machine {
+        Machine::from_image(Arc::new(program.to_vec()))
+    }
+
+    /// A machine whose memory starts as `image`, copied on its first write.
+    pub fn from_image(image: Arc<Vec<i64>>) -> Machine {
+        Machine {
+            memory: image,
+            ip: 0,
+            relative_base: 0,
+            inputs: VecDeque::new(),
+            halted: false,
+            instruction_count: 0,
+            instruction_limit: None,
+            memory_limit: MEMORY_LIMIT,
+            writers: None,
+            extensions: None,
+            protection: None,
+        }
+    }
+
+    /// A machine which also understands the op codes in `extensions`.
+    pub fn with_extensions(program: &[i64], extensions: Arc<extension::Registry>) -> Machine {
+        Machine { extensions: Some(extensions), ..Machine::new(program) }
+    }
+
+    /// Checks every later memory access against `policy`.
+    pub fn set_protection(&mut self, policy: protection::Policy) {
+        self.protection = Some(policy)
+    }
+
+    pub fn memory(&self) -> &[i64] {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut [i64] {
+        Arc::make_mut(&mut self.memory).as_mut_slice()
+    }
+
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    pub fn relative_base(&self) -> i64 {
+        self.relative_base
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Number of instructions executed so far.
+    pub fn instruction_count(&self) -> u64 {
+        self.instruction_count
+    }
+
+    /// Fails with `Error::InstructionLimit` instead of executing more than
+    /// `limit` instructions in total.
+    pub fn set_instruction_limit(&mut self, limit: u64) {
+        self.instruction_limit = Some(limit)
+    }
+
+    /// Fails with `Error::InvalidAddress` instead of accessing an address at
+    /// or beyond `limit`, memory grows on writes up to that point.
+    pub fn set_memory_limit(&mut self, limit: usize) {
+        self.memory_limit = limit
+    }
+
+    /// Starts recording which instruction writes each memory cell.
+    pub fn track_writes(&mut self) {
+        if self.writers.is_none() {
+            self.writers = Some(vec![None; self.memory.len()]);
+        }
+    }
+
+    /// Address of the instruction which last wrote `address`, if writes are tracked.
+    pub fn last_writer(&self, address: usize) -> Option<usize> {
+        self.writers.as_ref()?.get(address).cloned().flatten()
+    }
+
+    pub fn push_input(&mut self, value: i64) {
+        self.inputs.push_back(value)
+    }
+
+    /// Runs until the program produces an output, needs an input or halts.
+    pub fn run(&mut self) -> Result<State, Error> {
+        loop {
+            if let Some(state) = self.step()? {
+                return Ok(state);
+            }
+        }
+    }
+
+    /// Feeds all `inputs` and runs the program to the end, collecting outputs.
+    pub fn run_with(&mut self, inputs: &[i64]) -> Result<Vec<i64>, Error> {
+        self.inputs.extend(inputs);
+        let mut outputs = Vec::new();
+        loop {
+            match self.run()? {
+                State::Output(value) => outputs.push(value),
+                State::AwaitingInput => return Err(Error::EndOfInput { ip: self.ip }),
+                State::Halted => return Ok(outputs),
+            }
+        }
+    }
+
+    /// Executes a single instruction, returning the state if the machine stopped.
+    pub fn step(&mut self) -> Result<Option<State>, Error> {
+        if self.halted {
+            return Ok(Some(State::Halted));
+        }
+        if self.instruction_limit.is_some_and(|limit| self.instruction_count >= limit) {
+            return Err(Error::InstructionLimit { ip: self.ip });
+        }
+        let instruction = self.read(self.ip, Access::Execute)?;
+        let operation_code = match instruction_to_op_code(instruction) {
+            Some(operation_code) => operation_code,
+            None => return self.step_extension(instruction),
+        };
         match operation_code {
-            OpCode::Add(mode_1, mode_2) => {
-                let left = get_actual_value(&data, mode_1, position + 1);
-                let right = get_actual_value(&data, mode_2, position + 2);
-                let destination = data[position + 3] as usize;
-                data[destination] = left + right;
-                position += 4;
-            }
-            OpCode::Multiply(mode_1, mode_2) => {
-                let left = get_actual_value(&data, mode_1, position + 1);
-                let right = get_actual_value(&data, mode_2, position + 2);
-                let destination = data[position + 3] as usize;
-                data[destination] = left * right;
-                position += 4;
-            }
-            OpCode::LessThan(mode_1, mode_2) => {
-                let left = get_actual_value(&data, mode_1, position + 1);
-                let right = get_actual_value(&data, mode_2, position + 2);
-                let destination = data[position + 3] as usize;
-                data[destination] = if left < right { 1 } else { 0 };
-                position += 4
+            OpCode::Add(mode_1, mode_2, mode_3) => {
+                let left = self.parameter(mode_1, 1)?;
+                let right = self.parameter(mode_2, 2)?;
+                let sum = left.checked_add(right).ok_or(Error::Overflow { ip: self.ip })?;
+                self.write_parameter(mode_3, 3, sum)?;
+                self.ip += 4;
+            }
+            OpCode::Multiply(mode_1, mode_2, mode_3) => {
+                let left = self.parameter(mode_1, 1)?;
+                let right = self.parameter(mode_2, 2)?;
+                let product = left.checked_mul(right).ok_or(Error::Overflow { ip: self.ip })?;
+                self.write_parameter(mode_3, 3, product)?;
+                self.ip += 4;
+            }
+            OpCode::LessThan(mode_1, mode_2, mode_3) => {
+                let left = self.parameter(mode_1, 1)?;
+                let right = self.parameter(mode_2, 2)?;
+                self.write_parameter(mode_3, 3, if left < right { 1 } else { 0 })?;
+                self.ip += 4
             }
             OpCode::JumpIfTrue(mode_1, mode_2) => {
-                let left = get_actual_value(&data, mode_1, position + 1);
+                let left = self.parameter(mode_1, 1)?;
                 if left != 0 {
-                    position = get_actual_value(&data, mode_2, position + 2) as usize;
+                    self.ip = self.jump_target(mode_2)?;
                 } else {
-                    position += 3
+                    self.ip += 3
                 }
             }
             OpCode::JumpIfFalse(mode_1, mode_2) => {
-                let left = get_actual_value(&data, mode_1, position + 1);
+                let left = self.parameter(mode_1, 1)?;
                 if left == 0 {
-                    position = get_actual_value(&data, mode_2, position + 2) as usize;
+                    self.ip = self.jump_target(mode_2)?;
                 } else {
-                    position += 3
+                    self.ip += 3
                 }
             }
-            OpCode::Equals(mode_1, mode_2) => {
-                let left = get_actual_value(&data, mode_1, position + 1);
-                let right = get_actual_value(&data, mode_2, position + 2);
-                let destination = data[position + 3] as usize;
-                data[destination] = if left == right { 1 } else { 0 };
-                position += 4
+            OpCode::Equals(mode_1, mode_2, mode_3) => {
+                let left = self.parameter(mode_1, 1)?;
+                let right = self.parameter(mode_2, 2)?;
+                self.write_parameter(mode_3, 3, if left == right { 1 } else { 0 })?;
+                self.ip += 4
+            }
+            OpCode::AdjustRelativeBase(mode) => {
+                let offset = self.parameter(mode, 1)?;
+                self.relative_base = self.relative_base.checked_add(offset).ok_or(Error::Overflow { ip: self.ip })?;
+                self.ip += 2
             }
-            OpCode::Save(_) => {
-                let destination = data[position + 1] as usize;
-                data[destination] = input();
-                position += 2
+            OpCode::Save(mode) => {
+                let input = match self.inputs.pop_front() {
+                    Some(input) => input,
+                    None => return Ok(Some(State::AwaitingInput)),
+                };
+                self.write_parameter(mode, 1, input)?;
+                self.ip += 2
             }
             OpCode::Output(mode) => {
-                let output = get_actual_value(&data, mode, position + 1);
-                position += 2;
-                return ProgramResult::Output(output, position);
+                let output = self.parameter(mode, 1)?;
+                self.ip += 2;
+                self.instruction_count += 1;
+                return Ok(Some(State::Output(output)));
+            }
+            OpCode::Halt => {
+                self.halted = true;
+                self.instruction_count += 1;
+                return Ok(Some(State::Halted));
             }
-            OpCode::Halt => return ProgramResult::Halt,
         }
+        self.instruction_count += 1;
+        Ok(None)
+    }
+
+    fn step_extension(&mut self, instruction: i64) -> Result<Option<State>, Error> {
+        let invalid = Error::InvalidInstruction { ip: self.ip, instruction };
+        let extension = match self.extensions.as_ref().and_then(|registry| registry.get(instruction % 100)) {
+            Some(extension) => extension.clone(),
+            None => return Err(invalid),
+        };
+        let mut params = instruction / 100;
+        let mut modes = Vec::new();
+        for _ in &extension.roles {
+            modes.push(match params % 10 {
+                0 => Mode::Position,
+                1 => Mode::Immediate,
+                2 => Mode::Relative,
+                _ => return Err(invalid),
+            });
+            params /= 10;
+        }
+        if params != 0 {
+            return Err(invalid);
+        }
+
+        // Operands are resolved before the handler runs; the handler's result
+        // (write count, jump target) is checked after it returns. Nothing in
+        // the machine changes until every check has passed.
+        let mut values = Vec::new();
+        let mut destinations = Vec::new();
+        for (index, (role, mode)) in extension.roles.iter().zip(&modes).enumerate() {
+            match role {
+                extension::Role::Read => values.push(self.parameter(*mode, index + 1)?),
+                extension::Role::Write => destinations.push(self.destination(*mode, index + 1)?),
+            }
+        }
+        let effect = (extension.handler)(&values).map_err(|message| Error::Extension { ip: self.ip, message })?;
+        if effect.writes.len() != destinations.len() {
+            let message = format!("expected {} values, got {}", destinations.len(), effect.writes.len());
+            return Err(Error::Extension { ip: self.ip, message });
+        }
+        let target = match effect.control {
+            extension::Control::Jump(target) => Some(self.address(target)?),
+            _ => None,
+        };
+        for (destination, value) in destinations.into_iter().zip(effect.writes) {
+            self.store(destination, value);
+        }
+
+        self.instruction_count += 1;
+        match effect.control {
+            extension::Control::Next => self.ip += 1 + modes.len(),
+            extension::Control::Jump(_) => self.ip = target.unwrap(),
+            extension::Control::Output(value) => {
+                self.ip += 1 + modes.len();
+                return Ok(Some(State::Output(value)));
+            }
+            extension::Control::Halt => {
+                self.halted = true;
+                return Ok(Some(State::Halted));
+            }
+        }
+        Ok(None)
+    }
+
+    fn address(&self, address: i64) -> Result<usize, Error> {
+        if address < 0 || address as u64 >= self.memory_limit as u64 {
+            return Err(Error::InvalidAddress { ip: self.ip, address });
+        }
+        Ok(address as usize)
+    }
+
+    fn relative_address(&self, offset: i64) -> Result<usize, Error> {
+        let address = self.relative_base.checked_add(offset).ok_or(Error::Overflow { ip: self.ip })?;
+        self.address(address)
+    }
+
+    fn check(&self, address: usize, access: Access) -> Result<(), Error> {
+        match &self.protection {
+            Some(policy) if !policy.allows(address, access) => {
+                Err(Error::Protection { ip: self.ip, address, access })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Memory past the end of the program reads as zero.
+    fn read(&self, address: usize, access: Access) -> Result<i64, Error> {
+        self.check(address, access)?;
+        Ok(self.memory.get(address).cloned().unwrap_or(0))
+    }
+
+    fn parameter(&self, mode: Mode, offset: usize) -> Result<i64, Error> {
+        let value = self.read(self.ip + offset, Access::Execute)?;
+        match mode {
+            Mode::Position => self.read(self.address(value)?, Access::Read),
+            Mode::Immediate => Ok(value),
+            Mode::Relative => self.read(self.relative_address(value)?, Access::Read),
+        }
+    }
+
+    /// The address a parameter writes to, if writing there is allowed.
+    /// Writes never use immediate mode, such a parameter is treated as a position.
+    fn destination(&self, mode: Mode, offset: usize) -> Result<usize, Error> {
+        let destination = self.read(self.ip + offset, Access::Execute)?;
+        let destination = match mode {
+            Mode::Position | Mode::Immediate => self.address(destination)?,
+            Mode::Relative => self.relative_address(destination)?,
+        };
+        self.check(destination, Access::Write)?;
+        Ok(destination)
+    }
+
+    fn write_parameter(&mut self, mode: Mode, offset: usize, value: i64) -> Result<(), Error> {
+        let destination = self.destination(mode, offset)?;
+        self.store(destination, value);
+        Ok(())
+    }
+
+    fn store(&mut self, destination: usize, value: i64) {
+        let memory = Arc::make_mut(&mut self.memory);
+        if destination >= memory.len() {
+            memory.resize(destination + 1, 0);
+        }
+        memory[destination] = value;
+        if let Some(writers) = &mut self.writers {
+            if destination >= writers.len() {
+                writers.resize(destination + 1, None);
+            }
+            writers[destination] = Some(self.ip);
+        }
+    }
+
+    fn jump_target(&self, mode: Mode) -> Result<usize, Error> {
+        let target = self.parameter(mode, 2)?;
+        self.address(target)
+    }
+}
+
+/// The alternate form adds a view of the whole memory, without instruction
+/// markers.
+impl fmt::Debug for Machine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Machine")
+            .field("ip", &self.ip)
+            .field("relative_base", &self.relative_base)
+            .field("halted", &self.halted)
+            .field("instruction_count", &self.instruction_count)
+            .field("inputs", &self.inputs)
+            .finish()?;
+        if f.alternate() {
+            write!(f, "\n{}", viewer::View::new(self, 8).without_instructions())?;
+        }
+        Ok(())
     }
 }
 
@@ -155,74 +568,213 @@ mod tests {
 
         #[test]
         fn test_halt() {
-            assert_eq!(instruction_to_op_code(99), OpCode::Halt);
+            assert_eq!(instruction_to_op_code(99), Some(OpCode::Halt));
         }
 
         #[test]
         fn test_sum() {
-            assert_eq!(instruction_to_op_code(1), OpCode::Add(Mode::Position, Mode::Position));
-            assert_eq!(instruction_to_op_code(1001), OpCode::Add(Mode::Position, Mode::Immediate));
-            assert_eq!(instruction_to_op_code(101), OpCode::Add(Mode::Immediate, Mode::Position));
-            assert_eq!(instruction_to_op_code(1101), OpCode::Add(Mode::Immediate, Mode::Immediate));
+            assert_eq!(instruction_to_op_code(1), Some(OpCode::Add(Mode::Position, Mode::Position, Mode::Position)));
+            assert_eq!(instruction_to_op_code(1001), Some(OpCode::Add(Mode::Position, Mode::Immediate, Mode::Position)));
+            assert_eq!(instruction_to_op_code(101), Some(OpCode::Add(Mode::Immediate, Mode::Position, Mode::Position)));
+            assert_eq!(instruction_to_op_code(1101), Some(OpCode::Add(Mode::Immediate, Mode::Immediate, Mode::Position)));
         }
 
         #[test]
         fn test_multiply() {
-            assert_eq!(instruction_to_op_code(2), OpCode::Multiply(Mode::Position, Mode::Position));
-            assert_eq!(instruction_to_op_code(1002), OpCode::Multiply(Mode::Position, Mode::Immediate));
-            assert_eq!(instruction_to_op_code(102), OpCode::Multiply(Mode::Immediate, Mode::Position));
-            assert_eq!(instruction_to_op_code(1102), OpCode::Multiply(Mode::Immediate, Mode::Immediate));
+            assert_eq!(instruction_to_op_code(2), Some(OpCode::Multiply(Mode::Position, Mode::Position, Mode::Position)));
+            assert_eq!(instruction_to_op_code(1002), Some(OpCode::Multiply(Mode::Position, Mode::Immediate, Mode::Position)));
+            assert_eq!(instruction_to_op_code(102), Some(OpCode::Multiply(Mode::Immediate, Mode::Position, Mode::Position)));
+            assert_eq!(instruction_to_op_code(1102), Some(OpCode::Multiply(Mode::Immediate, Mode::Immediate, Mode::Position)));
         }
 
         #[test]
         fn test_save() {
-            assert_eq!(instruction_to_op_code(3), OpCode::Save(Mode::Position));
-            assert_eq!(instruction_to_op_code(103), OpCode::Save(Mode::Immediate));
+            assert_eq!(instruction_to_op_code(3), Some(OpCode::Save(Mode::Position)));
+            assert_eq!(instruction_to_op_code(103), Some(OpCode::Save(Mode::Immediate)));
         }
 
+        #[test]
         fn test_output() {
-            assert_eq!(instruction_to_op_code(4), OpCode::Output(Mode::Position));
-            assert_eq!(instruction_to_op_code(104), OpCode::Output(Mode::Immediate));
+            assert_eq!(instruction_to_op_code(4), Some(OpCode::Output(Mode::Position)));
+            assert_eq!(instruction_to_op_code(104), Some(OpCode::Output(Mode::Immediate)));
+        }
+
+        #[test]
+        fn test_relative() {
+            assert_eq!(instruction_to_op_code(203), Some(OpCode::Save(Mode::Relative)));
+            assert_eq!(instruction_to_op_code(109), Some(OpCode::AdjustRelativeBase(Mode::Immediate)));
+            assert_eq!(instruction_to_op_code(21201), Some(OpCode::Add(Mode::Relative, Mode::Immediate, Mode::Relative)));
+            assert_eq!(instruction_to_op_code(2105), Some(OpCode::JumpIfTrue(Mode::Immediate, Mode::Relative)));
+        }
+
+        #[test]
+        fn test_unknown() {
+            assert_eq!(instruction_to_op_code(42), None);
+            assert_eq!(instruction_to_op_code(301), None);
+            assert_eq!(instruction_to_op_code(21005), None);
         }
     }
 
+    fn run_to_end(data: &[i64]) -> Vec<i64> {
+        let mut machine = Machine::new(data);
+        machine.run_with(&[]).unwrap();
+        machine.memory().to_vec()
+    }
+
+    #[test]
+    fn test_parse_numbers() {
+        assert_eq!(parse_numbers(" 1, -2,3\n"), Ok(vec![1, -2, 3]));
+        assert_eq!(parse_numbers("  \n"), Ok(vec![]));
+        assert_eq!(parse_numbers("1,,2"), Err("invalid number \"\"".to_owned()));
+        assert_eq!(parse_numbers("1, x "), Err("invalid number \"x\"".to_owned()));
+    }
+
     #[test]
     fn test_sum() {
-        let mut data = vec![1, 0, 0, 0, 99];
+        let data = vec![1, 0, 0, 0, 99];
         let expected_output = vec![2, 0, 0, 0, 99];
-        let _output = program(&mut data, ||0);
-        assert_eq!(data, expected_output)
+        assert_eq!(run_to_end(&data), expected_output)
     }
 
     #[test]
     fn test_multiply() {
-        let mut data = vec![2, 3, 0, 3, 99];
+        let data = vec![2, 3, 0, 3, 99];
         let expected_output = vec![2, 3, 0, 6, 99];
-        program(&mut data, ||0);
-        assert_eq!(data, expected_output)
+        assert_eq!(run_to_end(&data), expected_output)
     }
 
     #[test]
     fn test_store_after() {
-        let mut data = vec![2, 4, 4, 5, 99, 0];
+        let data = vec![2, 4, 4, 5, 99, 0];
         let expected_output = vec![2, 4, 4, 5, 99, 9801];
-        program(&mut data, ||0);
-        assert_eq!(data, expected_output)
+        assert_eq!(run_to_end(&data), expected_output)
     }
 
     #[test]
     fn test_two_operations() {
-        let mut data = vec![1, 1, 1, 4, 99, 5, 6, 0, 99];
+        let data = vec![1, 1, 1, 4, 99, 5, 6, 0, 99];
         let expected_output = vec![30, 1, 1, 4, 2, 5, 6, 0, 99];
-        program(&mut data, ||0);
-        assert_eq!(data, expected_output)
+        assert_eq!(run_to_end(&data), expected_output)
     }
 
     #[test]
     fn test_just_with_input() {
-        let mut data = vec![3, 0, 4, 0, 99];
-        let input = ||31337;
-        let output = program(&mut data, input);
-        assert_eq!(31337, output.unwrap());
+        let mut machine = Machine::new(&[3, 0, 4, 0, 99]);
+        assert_eq!(machine.run(), Ok(State::AwaitingInput));
+        machine.push_input(31337);
+        assert_eq!(machine.run(), Ok(State::Output(31337)));
+        assert_eq!(machine.run(), Ok(State::Halted));
+    }
+
+    #[test]
+    fn test_instruction_count() {
+        let mut machine = Machine::new(&[3, 0, 4, 0, 99]);
+        assert_eq!(machine.run(), Ok(State::AwaitingInput));
+        assert_eq!(machine.instruction_count(), 0);
+        machine.push_input(1);
+        machine.run_with(&[]).unwrap();
+        assert_eq!(machine.instruction_count(), 3);
+    }
+
+    #[test]
+    fn test_instruction_limit() {
+        let mut machine = Machine::new(&[1105, 1, 0]);
+        machine.set_instruction_limit(10);
+        assert_eq!(machine.run(), Err(Error::InstructionLimit { ip: 0 }));
+        assert_eq!(machine.instruction_count(), 10);
+
+        let mut machine = Machine::new(&[104, 1, 99]);
+        machine.set_instruction_limit(2);
+        assert_eq!(machine.run_with(&[]), Ok(vec![1]));
+    }
+
+    #[test]
+    fn test_last_writer() {
+        let mut machine = Machine::new(&[1101, 1, 1, 11, 3, 12, 1, 11, 12, 11, 99, 0, 0]);
+        machine.track_writes();
+        assert_eq!(machine.run_with(&[5]), Ok(vec![]));
+        assert_eq!(machine.memory()[11], 7);
+        assert_eq!(machine.last_writer(11), Some(6));
+        assert_eq!(machine.last_writer(12), Some(4));
+        assert_eq!(machine.last_writer(0), None);
+    }
+
+    #[test]
+    fn test_copy_on_write() {
+        let image = Arc::new(vec![1101, 1, 1, 5, 99, 0]);
+        let mut machine = Machine::from_image(image.clone());
+        let reader = machine.clone();
+        assert_eq!(Arc::strong_count(&image), 3);
+        machine.run_with(&[]).unwrap();
+        assert_eq!(Arc::strong_count(&image), 2);
+        assert_eq!(machine.memory()[5], 2);
+        assert_eq!(reader.memory()[5], 0);
+    }
+
+    #[test]
+    fn test_end_of_input() {
+        let mut machine = Machine::new(&[3, 0, 4, 0, 99]);
+        assert_eq!(machine.run_with(&[]), Err(Error::EndOfInput { ip: 0 }));
+    }
+
+    #[test]
+    fn test_invalid_instruction() {
+        let mut machine = Machine::new(&[1101, 1, 1, 5, 42, 0]);
+        assert_eq!(machine.run(), Err(Error::InvalidInstruction { ip: 4, instruction: 42 }));
+    }
+
+    #[test]
+    fn test_invalid_address() {
+        let mut machine = Machine::new(&[1, 0, -1, 0, 99]);
+        assert_eq!(machine.run(), Err(Error::InvalidAddress { ip: 0, address: -1 }));
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut machine = Machine::new(&[1101, i64::MAX, 1, 0, 99]);
+        assert_eq!(machine.run(), Err(Error::Overflow { ip: 0 }));
+        let mut machine = Machine::new(&[1102, i64::MIN, -1, 0, 99]);
+        assert_eq!(machine.run(), Err(Error::Overflow { ip: 0 }));
+        let mut machine = Machine::new(&[109, i64::MAX, 109, 1, 99]);
+        assert_eq!(machine.run(), Err(Error::Overflow { ip: 2 }));
+        let mut machine = Machine::new(&[109, -1, 204, i64::MIN, 99]);
+        assert_eq!(machine.run(), Err(Error::Overflow { ip: 2 }));
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let mut machine = Machine::new(&[1101, 1, 1, 1_000_000_000_000_000, 99]);
+        assert_eq!(machine.run(), Err(Error::InvalidAddress { ip: 0, address: 1_000_000_000_000_000 }));
+
+        let mut machine = Machine::new(&[1, 0, 10, 0, 99]);
+        machine.set_memory_limit(10);
+        assert_eq!(machine.run(), Err(Error::InvalidAddress { ip: 0, address: 10 }));
+
+        let mut machine = Machine::new(&[1101, 1, 1, 9, 99]);
+        machine.set_memory_limit(10);
+        assert_eq!(machine.run(), Ok(State::Halted));
+        assert_eq!(machine.memory().len(), 10);
+    }
+
+    #[test]
+    fn test_quine() {
+        let program = vec![109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99];
+        assert_eq!(Machine::new(&program).run_with(&[]), Ok(program));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_large_numbers() {
+        let mut machine = Machine::new(&[1102, 34915192, 34915192, 7, 4, 7, 99, 0]);
+        assert_eq!(machine.run_with(&[]), Ok(vec![1219070632396864]));
+        let mut machine = Machine::new(&[104, 1125899906842624, 99]);
+        assert_eq!(machine.run_with(&[]), Ok(vec![1125899906842624]));
+    }
+
+    #[test]
+    fn test_relative_write_grows_memory() {
+        let mut machine = Machine::new(&[109, 10, 203, 2, 204, 2, 99]);
+        assert_eq!(machine.run_with(&[5]), Ok(vec![5]));
+        assert_eq!(machine.relative_base(), 10);
+        assert_eq!(machine.memory().len(), 13);
+    }
+}
@@ -0,0 +1,218 @@
+//! Helpers for programs which talk in ASCII.
+//!
+//! Such programs read and write text one character code at a time, lines end
+//! with a newline (10). Values outside of the ASCII range are usually answers
+//! and are kept apart from the text.
+
+use std::error;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use super::{Machine, State};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Output {
+    Line(String),
+    /// A value which is not an ASCII character code.
+    Value(i64),
+}
+
+fn is_ascii(value: i64) -> bool {
+    (0..128).contains(&value)
+}
+
+/// Turns output values into lines of text.
+#[derive(Debug, Default)]
+pub struct Decoder {
+    line: String,
+}
+
+impl Decoder {
+    pub fn new() -> Decoder {
+        Decoder::default()
+    }
+
+    /// Returns a line once its newline arrives, other values right away.
+    pub fn push(&mut self, value: i64) -> Option<Output> {
+        match value {
+            10 => Some(Output::Line(self.line.split_off(0))),
+            value if is_ascii(value) => {
+                self.line.push(value as u8 as char);
+                None
+            }
+            value => Some(Output::Value(value)),
+        }
+    }
+
+    /// The text after the last newline, e.g. a prompt.
+    pub fn flush(&mut self) -> Option<Output> {
+        if self.line.is_empty() {
+            return None;
+        }
+        Some(Output::Line(self.line.split_off(0)))
+    }
+}
+
+impl Machine {
+    /// Queues the character codes of `line` followed by a newline. Nothing is
+    /// queued if the line holds a character outside of ASCII.
+    pub fn push_line(&mut self, line: &str) -> Result<(), Error> {
+        if let Some(character) = line.chars().find(|character| !character.is_ascii()) {
+            return Err(Error::NotAscii(character));
+        }
+        for byte in line.bytes() {
+            self.push_input(byte as i64);
+        }
+        self.push_input(10);
+        Ok(())
+    }
+
+    /// Runs until the machine halts or waits for input, decoding its outputs.
+    /// An unfinished last line is returned as well.
+    pub fn run_ascii(&mut self) -> Result<(Vec<Output>, State), super::Error> {
+        let mut decoder = Decoder::new();
+        let mut outputs = Vec::new();
+        loop {
+            match self.run()? {
+                State::Output(value) => outputs.extend(decoder.push(value)),
+                state => {
+                    outputs.extend(decoder.flush());
+                    return Ok((outputs, state));
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Machine(super::Error),
+    Io(io::Error),
+    /// Input text can only hold ASCII characters.
+    NotAscii(char),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Machine(error) => write!(f, "{}", error),
+            Error::Io(error) => write!(f, "{}", error),
+            Error::NotAscii(character) => write!(f, "{:?} is not an ASCII character", character),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<super::Error> for Error {
+    fn from(error: super::Error) -> Error {
+        Error::Machine(error)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io(error)
+    }
+}
+
+/// Bridges the machine to a terminal: characters are written as they come,
+/// other values on their own line, and every input line is sent with its
+/// newline. A line which isn't ASCII is reported on `output` and asked for
+/// again. Returns `State::AwaitingInput` if `input` ends first.
+pub fn interact<R: BufRead, W: Write>(machine: &mut Machine, mut input: R, mut output: W) -> Result<State, Error> {
+    loop {
+        match machine.run()? {
+            State::Output(value) if is_ascii(value) => output.write_all(&[value as u8])?,
+            State::Output(value) => writeln!(output, "{}", value)?,
+            State::AwaitingInput => loop {
+                output.flush()?;
+                let mut line = String::new();
+                if input.read_line(&mut line)? == 0 {
+                    return Ok(State::AwaitingInput);
+                }
+                match machine.push_line(line.trim_end_matches(&['\r', '\n'][..])) {
+                    Ok(()) => break,
+                    Err(error) => write!(output, "{}, try again: ", error)?,
+                }
+            },
+            State::Halted => {
+                output.flush()?;
+                return Ok(State::Halted);
+            }
+        }
+    }
+}
+
+/// `interact` on stdin and stdout.
+pub fn terminal(machine: &mut Machine) -> Result<State, Error> {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    interact(machine, stdin.lock(), stdout.lock())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Prompts for a line, echoes it and ends with a non-ASCII value.
+    fn echo() -> Vec<i64> {
+        crate::intcode![
+            out #62;
+            read: in c;
+            out c;
+            eq c, #10 -> t;
+            jf t, #read;
+            out #1000;
+            halt;
+            c: 0;
+            t: 0
+        ]
+    }
+
+    #[test]
+    fn test_decoder() {
+        let mut decoder = Decoder::new();
+        let outputs: Vec<Output> = [104, 105, 10, 5000, 33].iter().filter_map(|value| decoder.push(*value)).collect();
+        assert_eq!(outputs, vec![Output::Line("hi".to_owned()), Output::Value(5000)]);
+        assert_eq!(decoder.flush(), Some(Output::Line("!".to_owned())));
+        assert_eq!(decoder.flush(), None);
+    }
+
+    #[test]
+    fn test_run_ascii() {
+        let mut machine = Machine::new(&echo());
+        assert_eq!(machine.run_ascii(), Ok((vec![Output::Line(">".to_owned())], State::AwaitingInput)));
+        machine.push_line("hello").unwrap();
+        assert_eq!(
+            machine.run_ascii(),
+            Ok((vec![Output::Line("hello".to_owned()), Output::Value(1000)], State::Halted))
+        );
+    }
+
+    #[test]
+    fn test_interact() {
+        let mut machine = Machine::new(&echo());
+        let mut output = Vec::new();
+        let state = interact(&mut machine, &b"hello\r\nignored\n"[..], &mut output).unwrap();
+        assert_eq!(state, State::Halted);
+        assert_eq!(String::from_utf8(output).unwrap(), ">hello\n1000\n");
+
+        let mut machine = Machine::new(&echo());
+        let state = interact(&mut machine, &b""[..], io::sink()).unwrap();
+        assert_eq!(state, State::AwaitingInput);
+
+        let mut machine = Machine::new(&echo());
+        let mut output = Vec::new();
+        let state = interact(&mut machine, "h\u{e9}\nhe\n".as_bytes(), &mut output).unwrap();
+        assert_eq!(state, State::Halted);
+        assert_eq!(String::from_utf8(output).unwrap(), ">'\u{e9}' is not an ASCII character, try again: he\n1000\n");
+    }
+
+    #[test]
+    fn test_non_ascii_line() {
+        let mut machine = Machine::new(&echo());
+        assert!(matches!(machine.push_line("na\u{ef}ve"), Err(Error::NotAscii('\u{ef}'))));
+        assert_eq!(machine.run_ascii(), Ok((vec![Output::Line(">".to_owned())], State::AwaitingInput)));
+    }
+}
@@ -0,0 +1,191 @@
+//! The `intcode!` macro, for writing intcode programs in Rust source.
+//!
+//! Items are separated by `;` and use the mnemonics of the disassembler:
+//!
+//! ```
+//! use advent_of_code_2019::intcode;
+//!
+//! let program = intcode![
+//!     in x;
+//!     loop_start: out x;
+//!     add x, #-1 -> x;
+//!     jt x, #loop_start;
+//!     halt;
+//!     x: 0
+//! ];
+//! assert_eq!(program, vec![3, 12, 4, 12, 1001, 12, -1, 12, 1005, 12, 2, 99, 0]);
+//! ```
+//!
+//! An operand is a position (`x` or `12`), an immediate value (`#-1` or
+//! `#loop_start`) or a relative cell (`rb[-1]`). A bare number is a data cell.
+//!
+//! Labels become constants holding their address, resolved by the compiler:
+//! an undefined label is a compile error and so is a label defined twice.
+//!
+//! ```compile_fail
+//! # use advent_of_code_2019::intcode;
+//! let program = intcode![x: out x; x: 0];
+//! ```
+//!
+//! `rb` is reserved for relative operands and can't be a label.
+//!
+//! ```compile_fail
+//! # use advent_of_code_2019::intcode;
+//! let program = intcode![out rb; rb: 0];
+//! ```
+
+/// Encodes an op code with its parameter modes.
+#[doc(hidden)]
+pub fn instruction(op_code: i64, modes: &[i64]) -> i64 {
+    modes.iter().rev().fold(0, |instruction, mode| instruction * 10 + mode) * 100 + op_code
+}
+
+#[macro_export]
+macro_rules! intcode {
+    // All items are parsed: define the labels and lay out the cells.
+    (@item [$(($label:ident $($size:tt)*))*] [$($cell:tt)*] [$($offset:tt)*]) => {{
+        $(
+            #[allow(dead_code, non_upper_case_globals)]
+            const $label: i64 = 0 $(+ $size)*;
+        )*
+        let program: Vec<i64> = vec![$($cell),*];
+        program
+    }};
+    (@item $labels:tt $cells:tt $offset:tt rb : $($rest:tt)*) => {
+        compile_error!("`rb` is reserved for relative operands and can't be a label")
+    };
+    (@item [$($labels:tt)*] $cells:tt [$($offset:tt)*] $label:ident : $($rest:tt)*) => {
+        $crate::intcode!(@item [$($labels)* ($label $($offset)*)] $cells [$($offset)*] $($rest)*)
+    };
+    (@item $labels:tt [$($cell:tt)*] [$($offset:tt)*] halt $(; $($rest:tt)*)?) => {
+        $crate::intcode!(@item $labels [$($cell)* (99)] [$($offset)* 1] $($($rest)*)?)
+    };
+    (@item $labels:tt [$($cell:tt)*] [$($offset:tt)*] $value:literal $(; $($rest:tt)*)?) => {
+        $crate::intcode!(@item $labels [$($cell)* ($value)] [$($offset)* 1] $($($rest)*)?)
+    };
+    (@item $labels:tt $cells:tt $offset:tt $mnemonic:ident $($rest:tt)*) => {
+        $crate::intcode!(@operand $labels $cells $offset $mnemonic [] $($rest)*)
+    };
+
+    // Operands of one instruction, collected as `(mode, value)`.
+    (@operand $labels:tt $cells:tt $offset:tt $mnemonic:ident [$($done:tt)*] # $value:literal $($rest:tt)*) => {
+        $crate::intcode!(@operand $labels $cells $offset $mnemonic [$($done)* (1, $value)] $($rest)*)
+    };
+    (@operand $labels:tt $cells:tt $offset:tt $mnemonic:ident [$($done:tt)*] # $label:ident $($rest:tt)*) => {
+        $crate::intcode!(@operand $labels $cells $offset $mnemonic [$($done)* (1, $label)] $($rest)*)
+    };
+    (@operand $labels:tt $cells:tt $offset:tt $mnemonic:ident [$($done:tt)*] rb [$value:literal] $($rest:tt)*) => {
+        $crate::intcode!(@operand $labels $cells $offset $mnemonic [$($done)* (2, $value)] $($rest)*)
+    };
+    (@operand $labels:tt $cells:tt $offset:tt $mnemonic:ident [$($done:tt)*] $label:ident $($rest:tt)*) => {
+        $crate::intcode!(@operand $labels $cells $offset $mnemonic [$($done)* (0, $label)] $($rest)*)
+    };
+    (@operand $labels:tt $cells:tt $offset:tt $mnemonic:ident [$($done:tt)*] $address:literal $($rest:tt)*) => {
+        $crate::intcode!(@operand $labels $cells $offset $mnemonic [$($done)* (0, $address)] $($rest)*)
+    };
+    (@operand $labels:tt $cells:tt $offset:tt $mnemonic:ident $done:tt , $($rest:tt)*) => {
+        $crate::intcode!(@operand $labels $cells $offset $mnemonic $done $($rest)*)
+    };
+    (@operand $labels:tt $cells:tt $offset:tt $mnemonic:ident $done:tt -> $($rest:tt)*) => {
+        $crate::intcode!(@operand $labels $cells $offset $mnemonic $done $($rest)*)
+    };
+    (@operand $labels:tt $cells:tt $offset:tt $mnemonic:ident $done:tt $(; $($rest:tt)*)?) => {
+        $crate::intcode!(@instruction $labels $cells $offset $mnemonic $done $($($rest)*)?)
+    };
+
+    (@instruction $labels:tt $cells:tt $offset:tt add [$left:tt $right:tt $result:tt] $($rest:tt)*) => {
+        $crate::intcode!(@encode $labels $cells $offset 1 [$left $right $result] $($rest)*)
+    };
+    (@instruction $labels:tt $cells:tt $offset:tt mul [$left:tt $right:tt $result:tt] $($rest:tt)*) => {
+        $crate::intcode!(@encode $labels $cells $offset 2 [$left $right $result] $($rest)*)
+    };
+    (@instruction $labels:tt $cells:tt $offset:tt in [$operand:tt] $($rest:tt)*) => {
+        $crate::intcode!(@encode $labels $cells $offset 3 [$operand] $($rest)*)
+    };
+    (@instruction $labels:tt $cells:tt $offset:tt out [$operand:tt] $($rest:tt)*) => {
+        $crate::intcode!(@encode $labels $cells $offset 4 [$operand] $($rest)*)
+    };
+    (@instruction $labels:tt $cells:tt $offset:tt jt [$condition:tt $target:tt] $($rest:tt)*) => {
+        $crate::intcode!(@encode $labels $cells $offset 5 [$condition $target] $($rest)*)
+    };
+    (@instruction $labels:tt $cells:tt $offset:tt jf [$condition:tt $target:tt] $($rest:tt)*) => {
+        $crate::intcode!(@encode $labels $cells $offset 6 [$condition $target] $($rest)*)
+    };
+    (@instruction $labels:tt $cells:tt $offset:tt lt [$left:tt $right:tt $result:tt] $($rest:tt)*) => {
+        $crate::intcode!(@encode $labels $cells $offset 7 [$left $right $result] $($rest)*)
+    };
+    (@instruction $labels:tt $cells:tt $offset:tt eq [$left:tt $right:tt $result:tt] $($rest:tt)*) => {
+        $crate::intcode!(@encode $labels $cells $offset 8 [$left $right $result] $($rest)*)
+    };
+    (@instruction $labels:tt $cells:tt $offset:tt arb [$operand:tt] $($rest:tt)*) => {
+        $crate::intcode!(@encode $labels $cells $offset 9 [$operand] $($rest)*)
+    };
+    (@instruction $labels:tt $cells:tt $offset:tt $mnemonic:ident $operands:tt $($rest:tt)*) => {
+        compile_error!(concat!("unknown mnemonic or wrong number of operands for `", stringify!($mnemonic), "`"))
+    };
+
+    (@encode $labels:tt [$($cell:tt)*] [$($offset:tt)*] $op_code:literal [$(($mode:literal, $value:tt))+] $($rest:tt)*) => {
+        $crate::intcode!(
+            @item
+            $labels
+            [$($cell)* ($crate::intcode::assembler::instruction($op_code, &[$($mode),+])) $(($value))+]
+            [$($offset)* (1 + [$($mode),+].len() as i64)]
+            $($rest)*
+        )
+    };
+
+    ($($items:tt)*) => {
+        $crate::intcode!(@item [] [] [] $($items)*)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Machine;
+
+    #[test]
+    fn test_instruction() {
+        assert_eq!(super::instruction(1, &[0, 1, 0]), 1001);
+        assert_eq!(super::instruction(9, &[2]), 209);
+        assert_eq!(super::instruction(99, &[]), 99);
+    }
+
+    #[test]
+    fn test_modes_and_labels() {
+        let program = intcode![
+            arb #10;
+            in rb[-1];
+            add rb[-1], #-4 -> x;
+            out x;
+            jf 0, #end;
+            out 7;
+            end: halt;
+            x: 0
+        ];
+        assert_eq!(program, vec![109, 10, 203, -1, 1201, -1, -4, 16, 4, 16, 1006, 0, 15, 4, 7, 99, 0]);
+    }
+
+    #[test]
+    fn test_feedback_amplifier() {
+        let program = intcode![
+            in phase;
+            add phase, #-4 -> phase;
+            next: in signal;
+            mul signal, #2 -> signal;
+            add signal, phase -> signal;
+            out signal;
+            add counter, #-1 -> counter;
+            jt counter, #next;
+            halt;
+            phase: 0;
+            signal: 0;
+            counter: 5
+        ];
+        assert_eq!(program, vec![
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1, 28, 1005, 28, 6, 99, 0,
+            0, 5
+        ]);
+        let mut machine = Machine::new(&program);
+        assert_eq!(machine.run_with(&[9, 1]), Err(super::super::Error::EndOfInput { ip: 6 }));
+    }
+}
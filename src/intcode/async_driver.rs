@@ -0,0 +1,352 @@
+//! Drives intcode machines from async code.
+//!
+//! Every machine reads its inputs from a `Receiver` and exposes its outputs as
+//! an async stream, so any number of machines can be wired together with
+//! channels and multiplexed on a single-threaded `Executor`.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+
+use super::{Error, Machine, State};
+
+struct Shared {
+    queue: VecDeque<i64>,
+    senders: usize,
+    waker: Option<Waker>,
+}
+
+/// Creates an unbounded channel of intcode values.
+pub fn channel() -> (Sender, Receiver) {
+    let shared = Rc::new(RefCell::new(Shared {
+        queue: VecDeque::new(),
+        senders: 1,
+        waker: None,
+    }));
+    (Sender { shared: shared.clone() }, Receiver { shared })
+}
+
+pub struct Sender {
+    shared: Rc<RefCell<Shared>>,
+}
+
+impl Sender {
+    pub fn send(&self, value: i64) {
+        let mut shared = self.shared.borrow_mut();
+        shared.queue.push_back(value);
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Clone for Sender {
+    fn clone(&self) -> Sender {
+        self.shared.borrow_mut().senders += 1;
+        Sender { shared: self.shared.clone() }
+    }
+}
+
+impl Drop for Sender {
+    fn drop(&mut self) {
+        let mut shared = self.shared.borrow_mut();
+        shared.senders -= 1;
+        if shared.senders == 0 {
+            if let Some(waker) = shared.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+pub struct Receiver {
+    shared: Rc<RefCell<Shared>>,
+}
+
+impl Receiver {
+    /// Waits for the next value, or `None` once every sender is gone.
+    pub async fn recv(&mut self) -> Option<i64> {
+        Recv { receiver: self }.await
+    }
+
+    fn poll_recv(&mut self, cx: &mut Context) -> Poll<Option<i64>> {
+        let mut shared = self.shared.borrow_mut();
+        if let Some(value) = shared.queue.pop_front() {
+            return Poll::Ready(Some(value));
+        }
+        if shared.senders == 0 {
+            return Poll::Ready(None);
+        }
+        shared.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+struct Recv<'a> {
+    receiver: &'a mut Receiver,
+}
+
+impl Future for Recv<'_> {
+    type Output = Option<i64>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<i64>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+/// A machine whose `Save` instructions wait on a channel instead of failing.
+pub struct AsyncMachine {
+    machine: Machine,
+    input: Receiver,
+}
+
+impl AsyncMachine {
+    pub fn new(machine: Machine, input: Receiver) -> AsyncMachine {
+        AsyncMachine { machine, input }
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    /// Waits for the next value on the input channel.
+    ///
+    /// A closed channel is reported as `Error::EndOfInput` at the current ip.
+    pub async fn read(&mut self) -> Result<i64, Error> {
+        let ip = self.machine.ip();
+        self.input.recv().await.ok_or(Error::EndOfInput { ip })
+    }
+
+    /// Turns the machine into the stream of its outputs.
+    pub fn outputs(self) -> Outputs {
+        Outputs { machine: self }
+    }
+
+    /// Runs the machine to the end, forwarding every output to `output`.
+    pub async fn pipe(self, output: Sender) -> Result<Machine, Error> {
+        let mut outputs = self.outputs();
+        while let Some(value) = outputs.next().await {
+            output.send(value?);
+        }
+        Ok(outputs.machine.machine)
+    }
+
+    fn poll_output(&mut self, cx: &mut Context) -> Poll<Option<Result<i64, Error>>> {
+        loop {
+            match self.machine.run() {
+                Ok(State::Output(value)) => return Poll::Ready(Some(Ok(value))),
+                Ok(State::Halted) => return Poll::Ready(None),
+                Ok(State::AwaitingInput) => match self.input.poll_recv(cx) {
+                    Poll::Ready(Some(value)) => self.machine.push_input(value),
+                    Poll::Ready(None) => {
+                        let ip = self.machine.ip();
+                        return Poll::Ready(Some(Err(Error::EndOfInput { ip })));
+                    }
+                    Poll::Pending => return Poll::Pending,
+                },
+                Err(error) => return Poll::Ready(Some(Err(error))),
+            }
+        }
+    }
+}
+
+/// Async stream of machine outputs, ending when the machine halts.
+pub struct Outputs {
+    machine: AsyncMachine,
+}
+
+impl Outputs {
+    pub async fn next(&mut self) -> Option<Result<i64, Error>> {
+        NextOutput { outputs: self }.await
+    }
+
+    pub fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Result<i64, Error>>> {
+        self.machine.poll_output(cx)
+    }
+
+    pub fn machine(&self) -> &Machine {
+        self.machine.machine()
+    }
+}
+
+struct NextOutput<'a> {
+    outputs: &'a mut Outputs,
+}
+
+impl Future for NextOutput<'_> {
+    type Output = Option<Result<i64, Error>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        Pin::new(&mut *self.outputs).poll_next(cx)
+    }
+}
+
+struct TaskWaker {
+    woken: AtomicBool,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.woken.store(true, Ordering::SeqCst);
+    }
+}
+
+struct Task {
+    future: Pin<Box<dyn Future<Output = ()>>>,
+    waker: Arc<TaskWaker>,
+}
+
+/// Single-threaded executor which polls spawned tasks round-robin.
+#[derive(Default)]
+pub struct Executor {
+    tasks: Vec<Task>,
+}
+
+impl Executor {
+    pub fn new() -> Executor {
+        Executor::default()
+    }
+
+    pub fn spawn<F>(&mut self, future: F) where
+        F: Future<Output = ()> + 'static {
+        self.tasks.push(Task {
+            future: Box::pin(future),
+            waker: Arc::new(TaskWaker { woken: AtomicBool::new(true) }),
+        });
+    }
+
+    /// Polls tasks until all of them complete or none of them can progress.
+    ///
+    /// Returns the number of tasks left blocked, so zero means every task
+    /// has finished and anything else is a deadlock.
+    pub fn run(&mut self) -> usize {
+        loop {
+            let mut progressed = false;
+            let mut index = 0;
+            while index < self.tasks.len() {
+                let task = &mut self.tasks[index];
+                if !task.waker.woken.swap(false, Ordering::SeqCst) {
+                    index += 1;
+                    continue;
+                }
+                progressed = true;
+                let waker = Waker::from(task.waker.clone());
+                let mut cx = Context::from_waker(&waker);
+                if task.future.as_mut().poll(&mut cx).is_ready() {
+                    self.tasks.remove(index);
+                } else {
+                    index += 1;
+                }
+            }
+            if !progressed {
+                return self.tasks.len();
+            }
+        }
+    }
+}
+
+/// Runs a single future to completion on the current thread.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let waker = Arc::new(TaskWaker { woken: AtomicBool::new(true) });
+    let mut future = Box::pin(future);
+    let cx_waker = Waker::from(waker.clone());
+    let mut cx = Context::from_waker(&cx_waker);
+    loop {
+        waker.woken.store(false, Ordering::SeqCst);
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        if !waker.woken.load(Ordering::SeqCst) {
+            panic!("block_on: future is blocked and nothing can wake it");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::Cell;
+
+    #[test]
+    fn test_outputs_stream() {
+        let (sender, receiver) = channel();
+        let machine = AsyncMachine::new(Machine::new(&[3, 0, 4, 0, 3, 0, 4, 0, 99]), receiver);
+        sender.send(7);
+        sender.send(8);
+        let outputs = block_on(async move {
+            let mut outputs = machine.outputs();
+            let mut values = Vec::new();
+            while let Some(value) = outputs.next().await {
+                values.push(value.unwrap());
+            }
+            values
+        });
+        assert_eq!(outputs, vec![7, 8]);
+    }
+
+    #[test]
+    fn test_closed_input() {
+        let (sender, receiver) = channel();
+        drop(sender);
+        let mut machine = AsyncMachine::new(Machine::new(&[3, 0, 99]), receiver);
+        assert_eq!(block_on(machine.read()), Err(Error::EndOfInput { ip: 0 }));
+        let result = block_on(async move { machine.outputs().next().await });
+        assert_eq!(result, Some(Err(Error::EndOfInput { ip: 0 })));
+    }
+
+    #[test]
+    fn test_feedback_loop() {
+        let program = vec![3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1, 28, 1005, 28, 6, 99, 0, 0, 5];
+        let configuration = vec![9, 8, 7, 6, 5];
+
+        let channels: Vec<(Sender, Receiver)> = configuration.iter().map(|_| channel()).collect();
+        let (senders, receivers): (Vec<Sender>, Vec<Receiver>) = channels.into_iter().unzip();
+        for (sender, phase) in senders.iter().zip(&configuration) {
+            sender.send(*phase);
+        }
+        senders[0].send(0);
+
+        let last_signal = Rc::new(Cell::new(None));
+        let mut executor = Executor::new();
+        for (index, receiver) in receivers.into_iter().enumerate() {
+            let machine = AsyncMachine::new(Machine::new(&program), receiver);
+            let next = senders[(index + 1) % configuration.len()].clone();
+            if index + 1 == configuration.len() {
+                let last_signal = last_signal.clone();
+                executor.spawn(async move {
+                    let mut outputs = machine.outputs();
+                    while let Some(value) = outputs.next().await {
+                        let value = value.unwrap();
+                        last_signal.set(Some(value));
+                        next.send(value);
+                    }
+                });
+            } else {
+                executor.spawn(async move {
+                    machine.pipe(next).await.unwrap();
+                });
+            }
+        }
+        drop(senders);
+
+        assert_eq!(executor.run(), 0);
+        assert_eq!(last_signal.get(), Some(139629729));
+    }
+
+    #[test]
+    fn test_deadlock() {
+        let (_sender, receiver) = channel();
+        let machine = AsyncMachine::new(Machine::new(&[3, 0, 99]), receiver);
+        let mut executor = Executor::new();
+        executor.spawn(async move {
+            machine.outputs().next().await;
+        });
+        assert_eq!(executor.run(), 1);
+    }
+}
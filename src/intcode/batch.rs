@@ -0,0 +1,167 @@
+//! Runs one program over many input configurations on several threads.
+//!
+//! Every job gets a fresh machine sharing the program image, memory is only
+//! copied once a job writes to it.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use super::Machine;
+
+pub struct Batch {
+    image: Arc<Vec<i64>>,
+    threads: usize,
+}
+
+impl Batch {
+    /// Uses as many threads as the system has cores.
+    pub fn new(program: &[i64]) -> Batch {
+        let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+        Batch::with_threads(program, threads)
+    }
+
+    pub fn with_threads(program: &[i64], threads: usize) -> Batch {
+        Batch { image: Arc::new(program.to_vec()), threads: threads.max(1) }
+    }
+
+    /// A fresh machine running the program.
+    pub fn machine(&self) -> Machine {
+        Machine::from_image(self.image.clone())
+    }
+
+    /// Runs `job` for every configuration and returns the results in the
+    /// order of the configurations.
+    pub fn map<C, T, I, F>(&self, configurations: I, job: F) -> Vec<T>
+    where
+        I: IntoIterator<Item = C>,
+        I::IntoIter: Send,
+        C: Send,
+        T: Send,
+        F: Fn(Machine, &C) -> T + Sync,
+    {
+        let mut results = Vec::new();
+        self.execute(configurations, &job, &|_: &T| false, &mut |index, _, result| results.push((index, result)));
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+
+    /// The first configuration in order whose result satisfies `predicate`,
+    /// along with the result. Configurations after a match are not started,
+    /// the ones before it still finish.
+    pub fn find<C, T, I, F, P>(&self, configurations: I, job: F, predicate: P) -> Option<(C, T)>
+    where
+        I: IntoIterator<Item = C>,
+        I::IntoIter: Send,
+        C: Send,
+        T: Send,
+        F: Fn(Machine, &C) -> T + Sync,
+        P: Fn(&T) -> bool + Sync,
+    {
+        let mut found: Option<(usize, C, T)> = None;
+        self.execute(configurations, &job, &predicate, &mut |index, configuration, result| {
+            if predicate(&result) && found.as_ref().is_none_or(|(first, _, _)| index < *first) {
+                found = Some((index, configuration, result));
+            }
+        });
+        found.map(|(_, configuration, result)| (configuration, result))
+    }
+
+    /// Workers take configurations from the shared iterator in order until
+    /// it is exhausted or they reach one past the first result satisfying
+    /// `stop`.
+    fn execute<C, T, I, F, P>(&self, configurations: I, job: &F, stop: &P, collect: &mut dyn FnMut(usize, C, T))
+    where
+        I: IntoIterator<Item = C>,
+        I::IntoIter: Send,
+        C: Send,
+        T: Send,
+        F: Fn(Machine, &C) -> T + Sync,
+        P: Fn(&T) -> bool + Sync,
+    {
+        let configurations = Mutex::new(configurations.into_iter().enumerate());
+        // Index of the first configuration known to satisfy `stop`.
+        let first = AtomicUsize::new(usize::MAX);
+        let worker = || {
+            let mut results = Vec::new();
+            loop {
+                let next = configurations.lock().unwrap().next();
+                let (index, configuration) = match next {
+                    Some((index, _)) if index > first.load(Ordering::Relaxed) => break,
+                    Some(next) => next,
+                    None => break,
+                };
+                let result = job(self.machine(), &configuration);
+                if stop(&result) {
+                    first.fetch_min(index, Ordering::Relaxed);
+                }
+                results.push((index, configuration, result));
+            }
+            results
+        };
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..self.threads).map(|_| scope.spawn(worker)).collect();
+            for worker in workers {
+                for (index, configuration, result) in worker.join().unwrap() {
+                    collect(index, configuration, result);
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Patches the last two cells, like the noun and verb of day 2, and
+    /// returns the resulting cell 0.
+    fn patch_job(mut machine: Machine, (noun, verb): &(i64, i64)) -> i64 {
+        let length = machine.memory().len();
+        machine.memory_mut()[length - 2] = *noun;
+        machine.memory_mut()[length - 1] = *verb;
+        machine.run_with(&[]).unwrap();
+        machine.memory()[0]
+    }
+
+    #[test]
+    fn test_map() {
+        let program = crate::intcode![in x; mul x, x -> x; out x; halt; x: 0];
+        let batch = Batch::with_threads(&program, 4);
+        let outputs = batch.map(0..100, |mut machine, input| machine.run_with(&[*input]).unwrap()[0]);
+        assert_eq!(outputs, (0..100).map(|input| input * input).collect::<Vec<i64>>());
+    }
+
+    #[test]
+    fn test_find() {
+        let program = crate::intcode![mul noun, #100 -> 0; add 0, verb -> 0; halt; noun: 0; verb: 0];
+        let batch = Batch::new(&program);
+        let pairs = (0..100).flat_map(|noun| (0..100).map(move |verb| (noun, verb)));
+        let found = batch.find(pairs, patch_job, |result| *result == 1202);
+        assert_eq!(found, Some(((12, 2), 1202)));
+        let pairs = (0..10).flat_map(|noun| (0..10).map(move |verb| (noun, verb)));
+        assert_eq!(batch.find(pairs, patch_job, |result| *result == -1), None);
+    }
+
+    #[test]
+    fn test_find_first_in_order() {
+        // Every input from 10 on matches, later ones finish sooner.
+        let program = crate::intcode![in x; again: add x, #-1 -> x; jt x, #again; halt; x: 0];
+        let batch = Batch::with_threads(&program, 4);
+        let job = |mut machine: Machine, input: &i64| {
+            machine.run_with(&[1000 - *input]).unwrap();
+            *input
+        };
+        for _ in 0..10 {
+            assert_eq!(batch.find(0..1000, job, |input| *input >= 10), Some((10, 10)));
+        }
+    }
+
+    #[test]
+    fn test_shared_image() {
+        let batch = Batch::with_threads(&[104, 1, 99], 2);
+        let machine = batch.machine();
+        assert_eq!(Arc::strong_count(&batch.image), 2);
+        assert_eq!(machine.memory(), &[104, 1, 99]);
+    }
+}
@@ -0,0 +1,256 @@
+//! Control flow graph recovery for intcode programs.
+//!
+//! Code is discovered by following execution from address zero. Jumps with a
+//! target taken from memory can't be followed statically, except for two
+//! idioms built around the relative base which compiled programs use for
+//! functions: a call stores the address after an unconditional jump into a
+//! relative cell right before the jump, and a return jumps to a relative cell.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::disasm::{decode, Instruction, Operand, Operation};
+
+/// How control leaves a basic block.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Exit {
+    /// Runs into the next block without a jump.
+    Fallthrough(usize),
+    Jump(usize),
+    Branch { taken: usize, fallthrough: usize },
+    Call { target: usize, ret: usize },
+    Return,
+    /// A jump through memory which isn't a return; the fallthrough is set for
+    /// conditional ones.
+    Indirect { fallthrough: Option<usize> },
+    Halt,
+    /// The next cell does not hold a valid instruction.
+    Invalid,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Block {
+    pub start: usize,
+    pub instructions: Vec<Instruction>,
+    pub exit: Exit,
+}
+
+impl Block {
+    /// Successors within the same function, calls continue at their return address.
+    pub fn successors(&self) -> Vec<usize> {
+        match self.exit {
+            Exit::Fallthrough(next) | Exit::Jump(next) => vec![next],
+            Exit::Branch { taken, fallthrough } => vec![taken, fallthrough],
+            Exit::Call { ret, .. } => vec![ret],
+            Exit::Indirect { fallthrough } => fallthrough.into_iter().collect(),
+            Exit::Return | Exit::Halt | Exit::Invalid => vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Cfg {
+    pub blocks: BTreeMap<usize, Block>,
+    /// Entry points of `main` (address zero) and of every called function.
+    pub functions: BTreeSet<usize>,
+}
+
+/// What an instruction does to control flow, if anything.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Flow {
+    Next,
+    Jump(usize),
+    Branch(usize),
+    Call(usize),
+    Return,
+    Indirect { conditional: bool },
+    Halt,
+}
+
+/// Classifies `instruction`, `previous` being the straight-line code before it.
+pub fn flow(instruction: &Instruction, previous: &[Instruction]) -> Flow {
+    let jump_if = match instruction.operation {
+        Operation::JumpIfTrue => true,
+        Operation::JumpIfFalse => false,
+        Operation::Halt => return Flow::Halt,
+        _ => return Flow::Next,
+    };
+    let always = match instruction.operands[0] {
+        Operand::Immediate(condition) if (condition != 0) == jump_if => true,
+        Operand::Immediate(_) => return Flow::Next,
+        _ => false,
+    };
+    match (instruction.operands[1], always) {
+        (Operand::Immediate(target), _) if target < 0 => Flow::Indirect { conditional: !always },
+        (Operand::Immediate(target), true) if stores_return_address(previous, instruction.end()) => {
+            Flow::Call(target as usize)
+        }
+        (Operand::Immediate(target), true) => Flow::Jump(target as usize),
+        (Operand::Immediate(target), false) => Flow::Branch(target as usize),
+        (Operand::Relative(_), true) => Flow::Return,
+        (_, always) => Flow::Indirect { conditional: !always },
+    }
+}
+
+/// True when one of the last few instructions puts `ret` into a relative cell.
+fn stores_return_address(previous: &[Instruction], ret: usize) -> bool {
+    previous.iter().rev().take(4).any(|instruction| {
+        // An overflowing store fails at run time, so it is no return address.
+        let value = match (instruction.operation, &instruction.operands[..]) {
+            (Operation::Add, [Operand::Immediate(left), Operand::Immediate(right), Operand::Relative(_)]) => {
+                left.checked_add(*right)
+            }
+            (Operation::Multiply, [Operand::Immediate(left), Operand::Immediate(right), Operand::Relative(_)]) => {
+                left.checked_mul(*right)
+            }
+            _ => return false,
+        };
+        value == Some(ret as i64)
+    })
+}
+
+impl Cfg {
+    pub fn recover(memory: &[i64]) -> Cfg {
+        let mut instructions: BTreeMap<usize, Instruction> = BTreeMap::new();
+        let mut leaders = BTreeSet::new();
+        let mut functions = BTreeSet::new();
+        let mut flows = BTreeMap::new();
+        let mut queue = vec![0];
+        leaders.insert(0);
+        functions.insert(0);
+        while let Some(start) = queue.pop() {
+            let mut address = start;
+            let mut run: Vec<Instruction> = Vec::new();
+            while !instructions.contains_key(&address) {
+                let instruction = match decode(memory, address) {
+                    Some(instruction) => instruction,
+                    None => break,
+                };
+                let next = instruction.end();
+                let flow = flow(&instruction, &run);
+                flows.insert(address, flow);
+                instructions.insert(address, instruction.clone());
+                run.push(instruction);
+                let mut targets = Vec::new();
+                match flow {
+                    Flow::Next => {
+                        address = next;
+                        continue;
+                    }
+                    Flow::Jump(target) => targets.push(target),
+                    Flow::Branch(target) => targets.extend(&[target, next]),
+                    Flow::Call(target) => {
+                        functions.insert(target);
+                        targets.extend(&[target, next]);
+                    }
+                    Flow::Indirect { conditional: true } => targets.push(next),
+                    Flow::Return | Flow::Indirect { conditional: false } | Flow::Halt => (),
+                }
+                for target in targets {
+                    leaders.insert(target);
+                    queue.push(target);
+                }
+                break;
+            }
+        }
+
+        let mut blocks = BTreeMap::new();
+        for start in &leaders {
+            let mut block_instructions = Vec::new();
+            let mut address = *start;
+            let exit = loop {
+                let instruction = match instructions.get(&address) {
+                    Some(instruction) => instruction.clone(),
+                    None => break Exit::Invalid,
+                };
+                let next = instruction.end();
+                let flow = flows[&address];
+                block_instructions.push(instruction);
+                match flow {
+                    Flow::Next if leaders.contains(&next) => break Exit::Fallthrough(next),
+                    Flow::Next => address = next,
+                    Flow::Jump(target) => break Exit::Jump(target),
+                    Flow::Branch(target) => break Exit::Branch { taken: target, fallthrough: next },
+                    Flow::Call(target) => break Exit::Call { target, ret: next },
+                    Flow::Return => break Exit::Return,
+                    Flow::Indirect { conditional } => {
+                        break Exit::Indirect { fallthrough: if conditional { Some(next) } else { None } }
+                    }
+                    Flow::Halt => break Exit::Halt,
+                }
+            };
+            blocks.insert(*start, Block { start: *start, instructions: block_instructions, exit });
+        }
+        Cfg { blocks, functions }
+    }
+
+    /// The decoded instruction starting at `address`, if it is reachable.
+    pub fn instruction(&self, address: usize) -> Option<&Instruction> {
+        let (_, block) = self.blocks.range(..=address).next_back()?;
+        block.instructions.iter().find(|instruction| instruction.address == address)
+    }
+
+    /// Blocks of the function starting at `entry`, without the functions it calls.
+    pub fn function_blocks(&self, entry: usize) -> BTreeSet<usize> {
+        let mut visited = BTreeSet::new();
+        let mut queue = vec![entry];
+        while let Some(start) = queue.pop() {
+            if !visited.insert(start) {
+                continue;
+            }
+            if let Some(block) = self.blocks.get(&start) {
+                queue.extend(block.successors());
+            }
+        }
+        visited.retain(|start| self.blocks.contains_key(start));
+        visited
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_if_else() {
+        let cfg = Cfg::recover(&[3, 14, 1005, 14, 10, 104, 0, 1105, 1, 12, 104, 1, 99, 0, 0]);
+        let exits: Vec<(usize, Exit)> = cfg.blocks.values().map(|block| (block.start, block.exit)).collect();
+        assert_eq!(exits, vec![
+            (0, Exit::Branch { taken: 10, fallthrough: 5 }),
+            (5, Exit::Jump(12)),
+            (10, Exit::Fallthrough(12)),
+            (12, Exit::Halt),
+        ]);
+        assert_eq!(cfg.functions, vec![0].into_iter().collect());
+    }
+
+    #[test]
+    fn test_call_and_return() {
+        let cfg = Cfg::recover(&[109, 50, 21101, 0, 9, 0, 1105, 1, 10, 99, 104, 42, 2105, 1, 0]);
+        assert_eq!(cfg.blocks[&0].exit, Exit::Call { target: 10, ret: 9 });
+        assert_eq!(cfg.blocks[&9].exit, Exit::Halt);
+        assert_eq!(cfg.blocks[&10].exit, Exit::Return);
+        assert_eq!(cfg.functions, vec![0, 10].into_iter().collect());
+        assert_eq!(cfg.function_blocks(0), vec![0, 9].into_iter().collect());
+        assert_eq!(cfg.function_blocks(10), vec![10].into_iter().collect());
+    }
+
+    #[test]
+    fn test_indirect_jump() {
+        let cfg = Cfg::recover(&[5, 7, 8, 99, 0, 0, 0, 1, 3]);
+        assert_eq!(cfg.blocks[&0].exit, Exit::Indirect { fallthrough: Some(3) });
+        assert_eq!(cfg.blocks[&3].exit, Exit::Halt);
+    }
+
+    #[test]
+    fn test_overflowing_return_address() {
+        // The store would overflow, so the jump is a plain one.
+        let program = vec![109, 50, 21101, i64::MAX, 1, 0, 1105, 1, 9, 99];
+        assert_eq!(Cfg::recover(&program).blocks[&0].exit, Exit::Jump(9));
+    }
+
+    #[test]
+    fn test_invalid() {
+        let cfg = Cfg::recover(&[1105, 1, 3, 42]);
+        assert_eq!(cfg.blocks[&3], Block { start: 3, instructions: vec![], exit: Exit::Invalid });
+    }
+}
@@ -0,0 +1,293 @@
+//! Conformance cases shared by every way of executing intcode.
+//!
+//! Cases are kept in text files, one block per case:
+//!
+//! ```text
+//! # comment
+//! case equal to 8
+//! program 3,9,8,9,10,9,4,9,99,-1,8
+//! input 8
+//! output 1
+//! ```
+//!
+//! Besides `program`, a case may list its `input`, the expected `output`, the
+//! first cells of the final `memory`, or the expected `error` message.
+
+use std::fs;
+
+use super::async_driver::{self, AsyncMachine};
+use super::symbolic::{self, Expr, SymbolicMachine};
+use super::{parse_numbers, partial, Machine, State};
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Case {
+    pub name: String,
+    pub program: Vec<i64>,
+    pub inputs: Vec<i64>,
+    pub outputs: Option<Vec<i64>>,
+    pub memory: Option<Vec<i64>>,
+    pub error: Option<String>,
+}
+
+pub fn parse(text: &str) -> Result<Vec<Case>, String> {
+    let mut cases: Vec<Case> = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = match line.find(' ') {
+            Some(position) => (&line[..position], line[position + 1..].trim()),
+            None => (line, ""),
+        };
+        if key == "case" {
+            cases.push(Case { name: value.to_owned(), ..Case::default() });
+            continue;
+        }
+        let error = |message: String| format!("line {}: {}", index + 1, message);
+        let case = cases.last_mut().ok_or_else(|| error("expected a case first".to_owned()))?;
+        match key {
+            "program" => case.program = parse_numbers(value).map_err(error)?,
+            "input" => case.inputs = parse_numbers(value).map_err(error)?,
+            "output" => case.outputs = Some(parse_numbers(value).map_err(error)?),
+            "memory" => case.memory = Some(parse_numbers(value).map_err(error)?),
+            "error" => case.error = Some(value.to_owned()),
+            _ => return Err(error(format!("unknown key {:?}", key))),
+        }
+    }
+    Ok(cases)
+}
+
+pub fn load(filename: &str) -> Result<Vec<Case>, String> {
+    let contents = fs::read_to_string(filename).map_err(|error| format!("{}: {}", filename, error))?;
+    parse(&contents).map_err(|error| format!("{}: {}", filename, error))
+}
+
+/// What a program left behind; engines which relocate the program have no
+/// comparable memory.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Outcome {
+    pub outputs: Vec<i64>,
+    pub memory: Option<Vec<i64>>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Failure {
+    /// The program failed, with the message of the error.
+    Error(String),
+    /// The program didn't finish within the instruction budget.
+    OutOfBudget,
+    /// The engine itself panicked, with the panic message.
+    Panic(String),
+}
+
+impl From<super::Error> for Failure {
+    fn from(error: super::Error) -> Failure {
+        match error {
+            super::Error::InstructionLimit { .. } => Failure::OutOfBudget,
+            error => Failure::Error(error.to_string()),
+        }
+    }
+}
+
+impl From<symbolic::Error> for Failure {
+    fn from(error: symbolic::Error) -> Failure {
+        match error {
+            symbolic::Error::StepLimit => Failure::OutOfBudget,
+            error => Failure::Error(error.to_string()),
+        }
+    }
+}
+
+/// Instructions a conformance case may execute.
+pub const BUDGET: u64 = 1_000_000;
+
+pub trait Engine {
+    fn name(&self) -> &'static str;
+
+    /// Runs `program` with all of `inputs`, executing at most `budget` instructions.
+    fn execute(&self, program: &[i64], inputs: &[i64], budget: u64) -> Result<Outcome, Failure>;
+
+    /// False when error messages refer to other addresses than the original program.
+    fn exact_errors(&self) -> bool {
+        true
+    }
+}
+
+pub struct Interpreter;
+
+impl Engine for Interpreter {
+    fn name(&self) -> &'static str {
+        "interpreter"
+    }
+
+    fn execute(&self, program: &[i64], inputs: &[i64], budget: u64) -> Result<Outcome, Failure> {
+        let mut machine = Machine::new(program);
+        machine.set_instruction_limit(budget);
+        let outputs = machine.run_with(inputs)?;
+        Ok(Outcome { outputs, memory: Some(machine.memory().to_vec()) })
+    }
+}
+
+pub struct Async;
+
+impl Engine for Async {
+    fn name(&self) -> &'static str {
+        "async"
+    }
+
+    fn execute(&self, program: &[i64], inputs: &[i64], budget: u64) -> Result<Outcome, Failure> {
+        let (sender, receiver) = async_driver::channel();
+        for input in inputs {
+            sender.send(*input);
+        }
+        drop(sender);
+        let mut machine = Machine::new(program);
+        machine.set_instruction_limit(budget);
+        let mut stream = AsyncMachine::new(machine, receiver).outputs();
+        let mut outputs = Vec::new();
+        while let Some(output) = async_driver::block_on(stream.next()) {
+            outputs.push(output?);
+        }
+        Ok(Outcome { outputs, memory: Some(stream.machine().memory().to_vec()) })
+    }
+}
+
+pub struct Symbolic;
+
+impl Engine for Symbolic {
+    fn name(&self) -> &'static str {
+        "symbolic"
+    }
+
+    fn execute(&self, program: &[i64], inputs: &[i64], budget: u64) -> Result<Outcome, Failure> {
+        let mut machine = SymbolicMachine::new(program);
+        for input in inputs {
+            machine.push_input(*input);
+        }
+        machine.run(budget as usize)?;
+        let constants = |values: &[Expr]| {
+            values
+                .iter()
+                .map(Expr::as_constant)
+                .collect::<Option<Vec<i64>>>()
+                .ok_or_else(|| Failure::Error("value is not a constant".to_owned()))
+        };
+        let outputs = constants(machine.outputs())?;
+        Ok(Outcome { outputs, memory: Some(constants(machine.memory())?) })
+    }
+}
+
+/// Specialises the program for all the inputs and runs the residual program.
+pub struct Specializer;
+
+impl Engine for Specializer {
+    fn name(&self) -> &'static str {
+        "specializer"
+    }
+
+    fn execute(&self, program: &[i64], inputs: &[i64], budget: u64) -> Result<Outcome, Failure> {
+        // Specialisation runs the program on the inputs without a limit, so
+        // make sure that run ends first.
+        let mut machine = Machine::new(program);
+        machine.set_instruction_limit(budget);
+        for input in inputs {
+            machine.push_input(*input);
+        }
+        while let State::Output(_) = machine.run()? {}
+
+        let (mut machine, mut outputs) = match partial::specialize(program, inputs)? {
+            partial::Specialized::Residual(residual) => (Machine::new(&residual), Vec::new()),
+            partial::Specialized::Snapshot { machine, outputs } => (machine, outputs),
+        };
+        machine.set_instruction_limit(machine.instruction_count() + budget);
+        outputs.extend(machine.run_with(&[])?);
+        Ok(Outcome { outputs, memory: None })
+    }
+
+    fn exact_errors(&self) -> bool {
+        false
+    }
+}
+
+pub fn engines() -> Vec<Box<dyn Engine>> {
+    vec![Box::new(Interpreter), Box::new(Async), Box::new(Symbolic), Box::new(Specializer)]
+}
+
+/// Runs `case` on `engine`, describing the first difference from the expectations.
+pub fn check(engine: &dyn Engine, case: &Case) -> Result<(), String> {
+    let outcome = match (engine.execute(&case.program, &case.inputs, BUDGET), &case.error) {
+        (Err(Failure::Error(actual)), Some(expected)) if !engine.exact_errors() || actual == *expected => {
+            return Ok(())
+        }
+        (Err(Failure::Error(actual)), _) => return Err(format!("failed with {:?}", actual)),
+        (Err(Failure::OutOfBudget), _) => return Err("ran out of budget".to_owned()),
+        (Err(Failure::Panic(message)), _) => return Err(format!("panicked: {}", message)),
+        (Ok(_), Some(expected)) => return Err(format!("expected error {:?}", expected)),
+        (Ok(outcome), None) => outcome,
+    };
+    if let Some(expected) = &case.outputs {
+        if outcome.outputs != *expected {
+            return Err(format!("expected outputs {:?}, got {:?}", expected, outcome.outputs));
+        }
+    }
+    if let (Some(expected), Some(actual)) = (&case.memory, &outcome.memory) {
+        let prefix = &actual[..expected.len().min(actual.len())];
+        if prefix != &expected[..] {
+            return Err(format!("expected memory {:?}, got {:?}", expected, prefix));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let cases = parse("# day 5\ncase echo\nprogram 3,0,4,0,99\ninput 7\noutput 7\n\ncase empty\nprogram 99\noutput\n");
+        assert_eq!(cases, Ok(vec![
+            Case {
+                name: "echo".to_owned(),
+                program: vec![3, 0, 4, 0, 99],
+                inputs: vec![7],
+                outputs: Some(vec![7]),
+                ..Case::default()
+            },
+            Case { name: "empty".to_owned(), program: vec![99], outputs: Some(vec![]), ..Case::default() },
+        ]));
+        assert_eq!(parse("program 99"), Err("line 1: expected a case first".to_owned()));
+        assert_eq!(parse("case x\nprogram 1,a"), Err("line 2: invalid number \"a\"".to_owned()));
+    }
+
+    #[test]
+    fn test_check() {
+        let case = Case { name: "wrong".to_owned(), program: vec![104, 1, 99], outputs: Some(vec![2]), ..Case::default() };
+        assert_eq!(check(&Interpreter, &case), Err("expected outputs [2], got [1]".to_owned()));
+    }
+
+    #[test]
+    fn test_conformance() {
+        let mut entries: Vec<_> = fs::read_dir("tests/intcode")
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        entries.sort();
+        let mut failures = Vec::new();
+        let mut count = 0;
+        for path in entries {
+            let filename = path.to_str().unwrap();
+            for case in load(filename).unwrap() {
+                count += 1;
+                for engine in engines() {
+                    if let Err(message) = check(engine.as_ref(), &case) {
+                        failures.push(format!("{} / {} / {}: {}", filename, case.name, engine.name(), message));
+                    }
+                }
+            }
+        }
+        assert!(count > 0);
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+}
@@ -0,0 +1,509 @@
+//! Lifts intcode into structured pseudo-code.
+//!
+//! Every function found by CFG recovery is printed on its own. Memory cells
+//! become variables named after their address (`v42`), relative cells are
+//! shown as `rb[n]`, and jump patterns are turned into `if`, `while` and
+//! `do`/`while` statements. Jumps which don't fit any pattern stay as `goto`.
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use super::cfg::{Cfg, Exit, Flow};
+use super::disasm::{Instruction, Operand, Operation};
+
+enum Statement {
+    Simple { address: usize, text: String },
+    Goto { address: usize, target: usize, condition: Option<String> },
+    If { address: usize, condition: String, then: Vec<Statement>, otherwise: Vec<Statement> },
+    While { address: usize, condition: String, body: Vec<Statement> },
+    DoWhile { address: usize, body: Vec<Statement>, condition: String },
+    Loop { address: usize, body: Vec<Statement> },
+}
+
+impl Statement {
+    fn address(&self) -> usize {
+        match self {
+            Statement::Simple { address, .. }
+            | Statement::Goto { address, .. }
+            | Statement::If { address, .. }
+            | Statement::While { address, .. }
+            | Statement::DoWhile { address, .. }
+            | Statement::Loop { address, .. } => *address,
+        }
+    }
+}
+
+fn operand(operand: &Operand) -> String {
+    match operand {
+        Operand::Immediate(value) => value.to_string(),
+        Operand::Position(address) => format!("v{}", address),
+        Operand::Relative(offset) => format!("rb[{}]", offset),
+    }
+}
+
+/// The condition under which a conditional jump is taken.
+fn jump_condition(instruction: &Instruction, negate: bool) -> String {
+    let jump_if = instruction.operation == Operation::JumpIfTrue;
+    let condition = operand(&instruction.operands[0]);
+    if jump_if != negate { condition } else { format!("!{}", condition) }
+}
+
+/// A decoded instruction, or a cell execution reaches which holds none.
+#[derive(Clone, Copy)]
+enum Entry<'a> {
+    Instruction(&'a Instruction),
+    Invalid(usize),
+}
+
+impl Entry<'_> {
+    fn address(&self) -> usize {
+        match self {
+            Entry::Instruction(instruction) => instruction.address,
+            Entry::Invalid(address) => *address,
+        }
+    }
+
+    fn end(&self) -> usize {
+        match self {
+            Entry::Instruction(instruction) => instruction.end(),
+            Entry::Invalid(address) => address + 1,
+        }
+    }
+}
+
+/// A function body as a list of its instructions in address order, an
+/// invalid cell stops the flow like a halt.
+struct Function<'a> {
+    entries: Vec<Entry<'a>>,
+    flows: Vec<Flow>,
+    /// Return address stores which are implied by a call.
+    hidden: BTreeSet<usize>,
+}
+
+impl<'a> Function<'a> {
+    fn new(cfg: &'a Cfg, entry: usize) -> Function<'a> {
+        let mut entries = Vec::new();
+        let mut flows = Vec::new();
+        let mut hidden = BTreeSet::new();
+        for start in cfg.function_blocks(entry) {
+            let block = &cfg.blocks[&start];
+            for (index, instruction) in block.instructions.iter().enumerate() {
+                entries.push(Entry::Instruction(instruction));
+                if index + 1 < block.instructions.len() {
+                    flows.push(Flow::Next);
+                    continue;
+                }
+                flows.push(match block.exit {
+                    Exit::Fallthrough(_) | Exit::Invalid => Flow::Next,
+                    Exit::Jump(target) => Flow::Jump(target),
+                    Exit::Branch { taken, .. } => Flow::Branch(taken),
+                    Exit::Call { target, ret } => {
+                        let store = block.instructions.iter().rev().skip(1).find(|instruction| {
+                            instruction.operation.writes()
+                                && matches!(instruction.operands[..], [Operand::Immediate(_), Operand::Immediate(_), Operand::Relative(_)])
+                                && constant(instruction) == Some(ret as i64)
+                        });
+                        if let Some(store) = store {
+                            hidden.insert(store.address);
+                        }
+                        Flow::Call(target)
+                    }
+                    Exit::Return => Flow::Return,
+                    Exit::Indirect { fallthrough } => Flow::Indirect { conditional: fallthrough.is_some() },
+                    Exit::Halt => Flow::Halt,
+                });
+            }
+            if block.exit == Exit::Invalid {
+                let address = block.instructions.last().map_or(start, |instruction| instruction.end());
+                entries.push(Entry::Invalid(address));
+                flows.push(Flow::Halt);
+            }
+        }
+        Function { entries, flows, hidden }
+    }
+
+    /// Index of the entry at `address`, or the length for the address right after the last one.
+    fn position(&self, address: usize) -> Option<usize> {
+        if let Some(last) = self.entries.last() {
+            if last.end() == address {
+                return Some(self.entries.len());
+            }
+        }
+        self.entries.iter().position(|entry| entry.address() == address)
+    }
+
+    /// The instruction at `index`, which has a jump flow.
+    fn jump(&self, index: usize) -> &'a Instruction {
+        match self.entries[index] {
+            Entry::Instruction(instruction) => instruction,
+            Entry::Invalid(address) => panic!("no instruction at {}", address),
+        }
+    }
+
+    fn jump_target(&self, index: usize) -> Option<usize> {
+        match self.flows[index] {
+            Flow::Jump(target) | Flow::Branch(target) => Some(target),
+            _ => None,
+        }
+    }
+
+    fn structure(&self, low: usize, high: usize) -> Vec<Statement> {
+        let mut statements = Vec::new();
+        let mut index = low;
+        while index < high {
+            let address = self.entries[index].address();
+
+            let back_edge = (index..high).rev().find(|&other| self.jump_target(other) == Some(address));
+            if let Some(end) = back_edge {
+                let statement = match self.flows[end] {
+                    Flow::Branch(_) => Statement::DoWhile {
+                        address,
+                        body: self.structure(index, end),
+                        condition: jump_condition(self.jump(end), false),
+                    },
+                    _ => match self.flows[index] {
+                        Flow::Branch(exit) if self.position(exit) == Some(end + 1) => Statement::While {
+                            address,
+                            condition: jump_condition(self.jump(index), true),
+                            body: self.structure(index + 1, end),
+                        },
+                        _ => Statement::Loop { address, body: self.structure(index, end) },
+                    },
+                };
+                statements.push(statement);
+                index = end + 1;
+                continue;
+            }
+
+            if let Flow::Branch(target) = self.flows[index] {
+                if let Some(join) = self.position(target).filter(|join| *join > index && *join <= high) {
+                    let condition = jump_condition(self.jump(index), true);
+                    let merge = match self.flows[join - 1] {
+                        Flow::Jump(merge) if join - 1 > index => {
+                            self.position(merge).filter(|merge| *merge > join && *merge <= high)
+                        }
+                        _ => None,
+                    };
+                    let statement = match merge {
+                        Some(merge) => {
+                            let statement = Statement::If {
+                                address,
+                                condition,
+                                then: self.structure(index + 1, join - 1),
+                                otherwise: self.structure(join, merge),
+                            };
+                            index = merge;
+                            statement
+                        }
+                        None => {
+                            let statement = Statement::If {
+                                address,
+                                condition,
+                                then: self.structure(index + 1, join),
+                                otherwise: vec![],
+                            };
+                            index = join;
+                            statement
+                        }
+                    };
+                    statements.push(statement);
+                    continue;
+                }
+            }
+
+            if !self.hidden.contains(&address) {
+                statements.push(self.lift(index));
+            }
+            index += 1;
+        }
+        statements
+    }
+
+    fn lift(&self, index: usize) -> Statement {
+        let instruction = match self.entries[index] {
+            Entry::Instruction(instruction) => instruction,
+            Entry::Invalid(address) => return Statement::Simple { address, text: "trap;".to_owned() },
+        };
+        let address = instruction.address;
+        let operands: Vec<String> = instruction.operands.iter().map(operand).collect();
+        let text = match (self.flows[index], instruction.operation) {
+            (Flow::Jump(target), _) => return Statement::Goto { address, target, condition: None },
+            (Flow::Branch(target), _) => {
+                let condition = Some(jump_condition(instruction, false));
+                return Statement::Goto { address, target, condition };
+            }
+            (Flow::Call(target), _) => format!("fn_{}();", target),
+            (Flow::Return, _) => "return;".to_owned(),
+            (Flow::Indirect { conditional: true }, _) => {
+                format!("if ({}) goto *{};", jump_condition(instruction, false), operands[1])
+            }
+            (Flow::Indirect { conditional: false }, _) => format!("goto *{};", operands[1]),
+            (Flow::Halt, _) => "halt;".to_owned(),
+            (Flow::Next, Operation::Add) => match instruction.operands[1] {
+                Operand::Immediate(value) if value < 0 => format!("{} = {} - {};", operands[2], operands[0], -value),
+                _ => format!("{} = {} + {};", operands[2], operands[0], operands[1]),
+            },
+            (Flow::Next, Operation::Multiply) => format!("{} = {} * {};", operands[2], operands[0], operands[1]),
+            (Flow::Next, Operation::LessThan) => format!("{} = {} < {};", operands[2], operands[0], operands[1]),
+            (Flow::Next, Operation::Equals) => format!("{} = {} == {};", operands[2], operands[0], operands[1]),
+            (Flow::Next, Operation::Input) => format!("{} = input();", operands[0]),
+            (Flow::Next, Operation::Output) => format!("output({});", operands[0]),
+            (Flow::Next, Operation::AdjustRelativeBase) => match instruction.operands[0] {
+                Operand::Immediate(value) if value < 0 => format!("rb -= {};", -value),
+                _ => format!("rb += {};", operands[0]),
+            },
+            // Jumps that are never taken.
+            (Flow::Next, _) => format!("// {}", instruction),
+        };
+        Statement::Simple { address, text }
+    }
+}
+
+fn constant(instruction: &Instruction) -> Option<i64> {
+    match (instruction.operation, &instruction.operands[..]) {
+        (Operation::Add, [Operand::Immediate(left), Operand::Immediate(right), _]) => left.checked_add(*right),
+        (Operation::Multiply, [Operand::Immediate(left), Operand::Immediate(right), _]) => left.checked_mul(*right),
+        _ => None,
+    }
+}
+
+/// Collects the address of every statement and the target of every `goto`.
+fn collect_labels(statements: &[Statement], addresses: &mut BTreeSet<usize>, targets: &mut BTreeSet<usize>) {
+    for statement in statements {
+        addresses.insert(statement.address());
+        match statement {
+            Statement::Goto { target, .. } => {
+                targets.insert(*target);
+            }
+            Statement::If { then, otherwise, .. } => {
+                collect_labels(then, addresses, targets);
+                collect_labels(otherwise, addresses, targets);
+            }
+            Statement::While { body, .. } | Statement::DoWhile { body, .. } | Statement::Loop { body, .. } => {
+                collect_labels(body, addresses, targets)
+            }
+            Statement::Simple { .. } => (),
+        }
+    }
+}
+
+/// A `goto` to `target`. Targets without a label are explained: they either
+/// lie outside the function or were folded into a loop or condition.
+fn goto(target: usize, labels: &BTreeSet<usize>, addresses: &[usize]) -> String {
+    if labels.contains(&target) {
+        format!("goto label_{};", target)
+    } else if addresses.contains(&target) {
+        format!("goto label_{}; // folded into a structured statement", target)
+    } else {
+        format!("goto label_{}; // outside this function", target)
+    }
+}
+
+/// Writes `statements` indented by `depth`, `addresses` are those of every
+/// instruction in the function.
+fn render(statements: &[Statement], labels: &BTreeSet<usize>, addresses: &[usize], depth: usize, output: &mut String) {
+    let indent = "    ".repeat(depth);
+    for statement in statements {
+        if labels.contains(&statement.address()) {
+            writeln!(output, "label_{}:", statement.address()).unwrap();
+        }
+        match statement {
+            Statement::Simple { text, .. } => writeln!(output, "{}{}", indent, text).unwrap(),
+            Statement::Goto { target, condition: None, .. } => {
+                writeln!(output, "{}{}", indent, goto(*target, labels, addresses)).unwrap()
+            }
+            Statement::Goto { target, condition: Some(condition), .. } => {
+                writeln!(output, "{}if ({}) {}", indent, condition, goto(*target, labels, addresses)).unwrap()
+            }
+            Statement::If { condition, then, otherwise, .. } => {
+                writeln!(output, "{}if ({}) {{", indent, condition).unwrap();
+                render(then, labels, addresses, depth + 1, output);
+                if !otherwise.is_empty() {
+                    writeln!(output, "{}}} else {{", indent).unwrap();
+                    render(otherwise, labels, addresses, depth + 1, output);
+                }
+                writeln!(output, "{}}}", indent).unwrap();
+            }
+            Statement::While { condition, body, .. } => {
+                writeln!(output, "{}while ({}) {{", indent, condition).unwrap();
+                render(body, labels, addresses, depth + 1, output);
+                writeln!(output, "{}}}", indent).unwrap();
+            }
+            Statement::DoWhile { body, condition, .. } => {
+                writeln!(output, "{}do {{", indent).unwrap();
+                render(body, labels, addresses, depth + 1, output);
+                writeln!(output, "{}}} while ({});", indent, condition).unwrap();
+            }
+            Statement::Loop { body, .. } => {
+                writeln!(output, "{}loop {{", indent).unwrap();
+                render(body, labels, addresses, depth + 1, output);
+                writeln!(output, "{}}}", indent).unwrap();
+            }
+        }
+    }
+}
+
+/// Decompiles every function reachable from address zero.
+pub fn decompile(memory: &[i64]) -> String {
+    let cfg = Cfg::recover(memory);
+    let mut output = String::new();
+    for entry in &cfg.functions {
+        let function = Function::new(&cfg, *entry);
+        let statements = function.structure(0, function.entries.len());
+        let (mut addresses, mut targets) = (BTreeSet::new(), BTreeSet::new());
+        collect_labels(&statements, &mut addresses, &mut targets);
+        let labels: BTreeSet<usize> = targets.intersection(&addresses).cloned().collect();
+
+        if !output.is_empty() {
+            output.push('\n');
+        }
+        let name = if *entry == 0 { "main".to_owned() } else { format!("fn_{}", entry) };
+        writeln!(output, "fn {}() {{", name).unwrap();
+        let addresses: Vec<usize> = function.entries.iter().map(|entry| entry.address()).collect();
+        render(&statements, &labels, &addresses, 1, &mut output);
+        writeln!(output, "}}").unwrap();
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_straight_line() {
+        let program = vec![3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0];
+        assert_eq!(decompile(&program), "\
+fn main() {
+    v15 = input();
+    v16 = input();
+    v16 = v16 * 10;
+    v15 = v16 + v15;
+    output(v15);
+    halt;
+}
+");
+    }
+
+    #[test]
+    fn test_if_else() {
+        let program = vec![3, 14, 1005, 14, 10, 104, 0, 1105, 1, 12, 104, 1, 99, 0, 0];
+        assert_eq!(decompile(&program), "\
+fn main() {
+    v14 = input();
+    if (!v14) {
+        output(0);
+    } else {
+        output(1);
+    }
+    halt;
+}
+");
+    }
+
+    #[test]
+    fn test_while() {
+        let program = vec![3, 15, 1006, 15, 14, 4, 15, 1001, 15, -1, 15, 1105, 1, 2, 99, 0];
+        assert_eq!(decompile(&program), "\
+fn main() {
+    v15 = input();
+    while (v15) {
+        output(v15);
+        v15 = v15 - 1;
+    }
+    halt;
+}
+");
+    }
+
+    #[test]
+    fn test_do_while() {
+        let program = vec![3, 13, 4, 13, 1001, 13, -1, 13, 1005, 13, 2, 99, 0, 0];
+        assert_eq!(decompile(&program), "\
+fn main() {
+    v13 = input();
+    do {
+        output(v13);
+        v13 = v13 - 1;
+    } while (v13);
+    halt;
+}
+");
+    }
+
+    #[test]
+    fn test_call_and_return() {
+        let program = vec![109, 50, 21101, 0, 9, 0, 1105, 1, 10, 99, 104, 42, 2105, 1, 0];
+        assert_eq!(decompile(&program), "\
+fn main() {
+    rb += 50;
+    fn_10();
+    halt;
+}
+
+fn fn_10() {
+    output(42);
+    return;
+}
+");
+        assert_eq!(super::super::Machine::new(&program).run_with(&[]), Ok(vec![42]));
+    }
+
+    #[test]
+    fn test_invalid() {
+        // Only the first branch runs into an invalid cell.
+        let program = vec![3, 11, 1005, 11, 9, 104, 1, 42, 0, 104, 2, 99];
+        assert_eq!(decompile(&program), "\
+fn main() {
+    v11 = input();
+    if (!v11) {
+        output(1);
+        trap;
+    }
+    output(2);
+    halt;
+}
+");
+        assert_eq!(decompile(&[1105, 1, 3, 42]), "\
+fn main() {
+    goto label_3;
+label_3:
+    trap;
+}
+");
+    }
+
+    #[test]
+    fn test_overflowing_constant() {
+        // Not a return address store, so it stays visible.
+        let program = vec![109, 50, 21101, i64::MAX, 1, 0, 1105, 1, 9, 99];
+        assert!(decompile(&program).contains(&format!("rb[0] = {} + 1;", i64::MAX)));
+        assert_eq!(constant(&super::super::disasm::decode(&program, 2).unwrap()), None);
+    }
+
+    #[test]
+    fn test_goto() {
+        // Jumps into the middle of the other branch.
+        let program = vec![3, 16, 1005, 16, 9, 104, 0, 104, 1, 104, 2, 1105, 1, 7, 99, 0, 0];
+        let decompiled = decompile(&program);
+        assert!(decompiled.contains("goto label_7;"));
+        assert!(decompiled.contains("label_7:\n"));
+    }
+
+    #[test]
+    fn test_unlabelled_target() {
+        // The jump at 7 becomes the loop itself, so the goto has no label to go to.
+        let program = vec![3, 9, 1105, 1, 7, 104, 1, 1105, 1, 5, 0];
+        assert_eq!(decompile(&program), "\
+fn main() {
+    v9 = input();
+    goto label_7; // folded into a structured statement
+    loop {
+        output(1);
+    }
+}
+");
+        let labels = BTreeSet::new();
+        assert_eq!(goto(30, &labels, &[10, 20]), "goto label_30; // outside this function");
+    }
+}
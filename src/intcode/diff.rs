@@ -0,0 +1,146 @@
+//! Differences between the memories of two machines.
+//!
+//! Cells which differ are grouped into runs of consecutive addresses. When the
+//! machines track writes, every cell is annotated with the instruction which
+//! wrote it last on either side, which points at the code an input influences.
+
+use std::fmt;
+
+use super::{Error, Machine, State};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Cell {
+    pub address: usize,
+    pub left: i64,
+    pub right: i64,
+    pub left_writer: Option<usize>,
+    pub right_writer: Option<usize>,
+}
+
+/// Consecutive differing cells.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Range {
+    pub start: usize,
+    pub end: usize,
+    pub cells: Vec<Cell>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Diff {
+    pub ranges: Vec<Range>,
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn cells(&self) -> impl Iterator<Item = &Cell> {
+        self.ranges.iter().flat_map(|range| range.cells.iter())
+    }
+}
+
+/// Compares the memories of `left` and `right`; cells past the end of the
+/// shorter memory count as zero.
+pub fn diff(left: &Machine, right: &Machine) -> Diff {
+    let length = left.memory().len().max(right.memory().len());
+    let value = |machine: &Machine, address: usize| machine.memory().get(address).cloned().unwrap_or(0);
+    let mut ranges: Vec<Range> = Vec::new();
+    for address in 0..length {
+        let cell = Cell {
+            address,
+            left: value(left, address),
+            right: value(right, address),
+            left_writer: left.last_writer(address),
+            right_writer: right.last_writer(address),
+        };
+        if cell.left == cell.right {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some(range) if range.end == address => {
+                range.end += 1;
+                range.cells.push(cell);
+            }
+            _ => ranges.push(Range { start: address, end: address + 1, cells: vec![cell] }),
+        }
+    }
+    Diff { ranges }
+}
+
+fn run_tracked(program: &[i64], inputs: &[i64]) -> Result<Machine, Error> {
+    let mut machine = Machine::new(program);
+    machine.track_writes();
+    for input in inputs {
+        machine.push_input(*input);
+    }
+    while let State::Output(_) = machine.run()? {}
+    Ok(machine)
+}
+
+/// Runs `program` once with each list of inputs, until it halts or waits for
+/// more input, and compares the resulting memories.
+pub fn diff_runs(program: &[i64], left_inputs: &[i64], right_inputs: &[i64]) -> Result<Diff, Error> {
+    let left = run_tracked(program, left_inputs)?;
+    let right = run_tracked(program, right_inputs)?;
+    Ok(diff(&left, &right))
+}
+
+fn writer(writer: Option<usize>) -> String {
+    writer.map_or_else(|| "-".to_owned(), |ip| ip.to_string())
+}
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for range in &self.ranges {
+            writeln!(f, "{}..{}:", range.start, range.end)?;
+            for cell in &range.cells {
+                writeln!(
+                    f,
+                    "{:>6}: {} | {}  written at {} | {}",
+                    cell.address,
+                    cell.left,
+                    cell.right,
+                    writer(cell.left_writer),
+                    writer(cell.right_writer)
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_runs() {
+        // Stores the input and its double, then a constant.
+        let program = crate::intcode![
+            in x;
+            mul x, #2 -> y;
+            add #1, #1 -> z;
+            halt;
+            x: 0;
+            y: 0;
+            z: 0
+        ];
+        let diff = diff_runs(&program, &[3], &[4]).unwrap();
+        assert_eq!(diff.ranges.len(), 1);
+        assert_eq!((diff.ranges[0].start, diff.ranges[0].end), (11, 13));
+        assert_eq!(diff.to_string(), "11..13:\n    11: 3 | 4  written at 0 | 0\n    12: 6 | 8  written at 2 | 2\n");
+        assert!(diff_runs(&program, &[3], &[3]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_ranges() {
+        let left = Machine::new(&[1, 2, 3, 4, 5]);
+        let right = Machine::new(&[1, 0, 0, 4, 5, 6]);
+        let diff = diff(&left, &right);
+        let ranges: Vec<(usize, usize)> = diff.ranges.iter().map(|range| (range.start, range.end)).collect();
+        assert_eq!(ranges, vec![(1, 3), (5, 6)]);
+        assert_eq!(diff.cells().last().unwrap().left, 0);
+        assert_eq!(diff.cells().next().unwrap().left_writer, None);
+    }
+}
@@ -0,0 +1,187 @@
+//! Static decoding of intcode programs.
+
+use std::fmt;
+
+use super::cfg::Cfg;
+use super::{instruction_to_op_code, Mode, OpCode};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Operation {
+    Add,
+    Multiply,
+    Input,
+    Output,
+    JumpIfTrue,
+    JumpIfFalse,
+    LessThan,
+    Equals,
+    AdjustRelativeBase,
+    Halt,
+}
+
+impl Operation {
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Operation::Add => "add",
+            Operation::Multiply => "mul",
+            Operation::Input => "in",
+            Operation::Output => "out",
+            Operation::JumpIfTrue => "jt",
+            Operation::JumpIfFalse => "jf",
+            Operation::LessThan => "lt",
+            Operation::Equals => "eq",
+            Operation::AdjustRelativeBase => "arb",
+            Operation::Halt => "halt",
+        }
+    }
+
+    /// True for operations whose last parameter is written to.
+    pub fn writes(self) -> bool {
+        matches!(self, Operation::Add | Operation::Multiply | Operation::Input | Operation::LessThan | Operation::Equals)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Operand {
+    Immediate(i64),
+    Position(i64),
+    Relative(i64),
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Immediate(value) => write!(f, "#{}", value),
+            Operand::Position(address) => write!(f, "[{}]", address),
+            Operand::Relative(offset) if *offset < 0 => write!(f, "[rb{}]", offset),
+            Operand::Relative(offset) => write!(f, "[rb+{}]", offset),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Instruction {
+    pub address: usize,
+    pub operation: Operation,
+    pub operands: Vec<Operand>,
+}
+
+impl Instruction {
+    /// Address of the cell right after the instruction.
+    pub fn end(&self) -> usize {
+        self.address + 1 + self.operands.len()
+    }
+}
+
+/// `add [10], #4 -> [10]`: the written parameter comes last, after an arrow.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.operation.mnemonic())?;
+        let (read, written) = if self.operation.writes() {
+            self.operands.split_at(self.operands.len() - 1)
+        } else {
+            (&self.operands[..], &[][..])
+        };
+        for (index, operand) in read.iter().enumerate() {
+            write!(f, "{}{}", if index == 0 { " " } else { ", " }, operand)?;
+        }
+        for operand in written {
+            write!(f, " -> {}", operand)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decodes the instruction at `address`, if the cell holds a valid one.
+pub fn decode(memory: &[i64], address: usize) -> Option<Instruction> {
+    let operation_code = instruction_to_op_code(*memory.get(address)?)?;
+    let operation = match operation_code {
+        OpCode::Add(_, _, _) => Operation::Add,
+        OpCode::Multiply(_, _, _) => Operation::Multiply,
+        OpCode::Save(_) => Operation::Input,
+        OpCode::Output(_) => Operation::Output,
+        OpCode::JumpIfTrue(_, _) => Operation::JumpIfTrue,
+        OpCode::JumpIfFalse(_, _) => Operation::JumpIfFalse,
+        OpCode::LessThan(_, _, _) => Operation::LessThan,
+        OpCode::Equals(_, _, _) => Operation::Equals,
+        OpCode::AdjustRelativeBase(_) => Operation::AdjustRelativeBase,
+        OpCode::Halt => Operation::Halt,
+    };
+    let mut operands = Vec::new();
+    for (index, mode) in operation_code.modes().into_iter().enumerate() {
+        let value = *memory.get(address + 1 + index)?;
+        operands.push(match mode {
+            Mode::Position => Operand::Position(value),
+            Mode::Immediate => Operand::Immediate(value),
+            Mode::Relative => Operand::Relative(value),
+        });
+    }
+    Some(Instruction { address, operation, operands })
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Line {
+    Instruction(Instruction),
+    Data { address: usize, value: i64 },
+}
+
+/// Disassembles the code reachable from address zero, every other cell is data.
+pub fn disassemble(memory: &[i64]) -> Vec<Line> {
+    let cfg = Cfg::recover(memory);
+    let mut lines = Vec::new();
+    let mut address = 0;
+    while address < memory.len() {
+        match cfg.instruction(address) {
+            Some(instruction) => {
+                address = instruction.end();
+                lines.push(Line::Instruction(instruction.clone()));
+            }
+            None => {
+                lines.push(Line::Data { address, value: memory[address] });
+                address += 1;
+            }
+        }
+    }
+    lines
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Line::Instruction(instruction) => write!(f, "{:>6}: {}", instruction.address, instruction),
+            Line::Data { address, value } => write!(f, "{:>6}: data {}", address, value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let memory = vec![1001, 10, -4, 10, 21101, 0, 7, -1, 109, 3];
+        assert_eq!(decode(&memory, 0).unwrap().to_string(), "add [10], #-4 -> [10]");
+        assert_eq!(decode(&memory, 4).unwrap().to_string(), "add #0, #7 -> [rb-1]");
+        assert_eq!(decode(&memory, 8).unwrap().to_string(), "arb #3");
+        assert_eq!(decode(&memory, 2), None);
+        assert_eq!(decode(&[1, 0, 0], 0), None);
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let lines: Vec<String> = disassemble(&[3, 10, 1005, 10, 7, 104, 0, 4, 10, 99, 0])
+            .iter()
+            .map(|line| line.to_string())
+            .collect();
+        assert_eq!(lines, vec![
+            "     0: in -> [10]",
+            "     2: jt [10], #7",
+            "     5: out #0",
+            "     7: out [10]",
+            "     9: halt",
+            "    10: data 0",
+        ]);
+        assert_eq!(disassemble(&[104, 1, 99, 5]).last(), Some(&Line::Data { address: 3, value: 5 }));
+    }
+}
@@ -0,0 +1,223 @@
+//! Extra op codes registered at machine construction.
+//!
+//! An extension declares its op code, the role of each parameter and a
+//! handler. The machine resolves parameter modes as for the built-in op codes,
+//! passes the values of the read parameters to the handler and stores the
+//! values it returns into the write parameters, in order.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Role {
+    Read,
+    Write,
+}
+
+/// Where execution continues after an extension instruction.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Control {
+    Next,
+    Jump(i64),
+    Output(i64),
+    Halt,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Effect {
+    pub writes: Vec<i64>,
+    pub control: Control,
+}
+
+/// Receives the values of the read parameters, a failure is reported as
+/// `intcode::Error::Extension` with the returned message.
+pub type Handler = Arc<dyn Fn(&[i64]) -> Result<Effect, String> + Send + Sync>;
+
+#[derive(Clone)]
+pub struct Extension {
+    pub op_code: i64,
+    pub roles: Vec<Role>,
+    pub handler: Handler,
+}
+
+impl Extension {
+    pub fn writes(&self) -> usize {
+        self.roles.iter().filter(|role| **role == Role::Write).count()
+    }
+}
+
+impl fmt::Debug for Extension {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Extension").field("op_code", &self.op_code).field("roles", &self.roles).finish()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Error {
+    /// Built-in op codes can't be redefined.
+    Builtin(i64),
+    AlreadyRegistered(i64),
+    /// Op codes are the last two digits of an instruction.
+    OutOfRange(i64),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Builtin(op_code) => write!(f, "op code {} is built in", op_code),
+            Error::AlreadyRegistered(op_code) => write!(f, "op code {} is already registered", op_code),
+            Error::OutOfRange(op_code) => write!(f, "op code {} is not between 1 and 98", op_code),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+const BUILTIN: [i64; 10] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 99];
+
+#[derive(Debug, Clone, Default)]
+pub struct Registry {
+    extensions: BTreeMap<i64, Extension>,
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        Registry::default()
+    }
+
+    pub fn register<F>(&mut self, op_code: i64, roles: &[Role], handler: F) -> Result<(), Error>
+    where
+        F: Fn(&[i64]) -> Result<Effect, String> + Send + Sync + 'static,
+    {
+        if BUILTIN.contains(&op_code) {
+            return Err(Error::Builtin(op_code));
+        }
+        if !(1..=98).contains(&op_code) {
+            return Err(Error::OutOfRange(op_code));
+        }
+        if self.extensions.contains_key(&op_code) {
+            return Err(Error::AlreadyRegistered(op_code));
+        }
+        let extension = Extension { op_code, roles: roles.to_vec(), handler: Arc::new(handler) };
+        self.extensions.insert(op_code, extension);
+        Ok(())
+    }
+
+    pub fn get(&self, op_code: i64) -> Option<&Extension> {
+        self.extensions.get(&op_code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::protection::{Access, Permissions, Policy};
+    use super::super::{Error as MachineError, Machine, State};
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn dialect() -> Registry {
+        let mut registry = Registry::new();
+        // 10: a % b -> c
+        registry
+            .register(10, &[Role::Read, Role::Read, Role::Write], |values| {
+                if values[1] == 0 {
+                    return Err("division by zero".to_owned());
+                }
+                Ok(Effect { writes: vec![values[0] % values[1]], control: Control::Next })
+            })
+            .unwrap();
+        // 11: unconditional jump
+        registry
+            .register(11, &[Role::Read], |values| Ok(Effect { writes: vec![], control: Control::Jump(values[0]) }))
+            .unwrap();
+        registry
+    }
+
+    #[test]
+    fn test_register() {
+        let mut registry = dialect();
+        let nop = |_: &[i64]| Ok(Effect { writes: vec![], control: Control::Next });
+        assert_eq!(registry.register(1, &[], nop), Err(Error::Builtin(1)));
+        assert_eq!(registry.register(99, &[], nop), Err(Error::Builtin(99)));
+        assert_eq!(registry.register(10, &[], nop), Err(Error::AlreadyRegistered(10)));
+        assert_eq!(registry.register(100, &[], nop), Err(Error::OutOfRange(100)));
+        assert_eq!(registry.register(0, &[], nop), Err(Error::OutOfRange(0)));
+        assert_eq!(registry.get(10).unwrap().writes(), 1);
+    }
+
+    #[test]
+    fn test_dialect() {
+        let program = vec![3, 14, 21010, 14, 7, 0, 111, 11, 104, 5, 99, 4, 0, 99, 0];
+        let registry = Arc::new(dialect());
+        let mut machine = Machine::with_extensions(&program, registry.clone());
+        // Stores the remainder through a relative parameter, then jumps over the output of 5.
+        assert_eq!(machine.run_with(&[23]), Ok(vec![2]));
+        assert_eq!(machine.memory()[0], 2);
+
+        let mut machine = Machine::with_extensions(&[1110, 0, 0, 0, 99], registry);
+        assert_eq!(
+            machine.run(),
+            Err(MachineError::Extension { ip: 0, message: "division by zero".to_owned() })
+        );
+    }
+
+    #[test]
+    fn test_checks_before_effects() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut registry = dialect();
+        let count = calls.clone();
+        // 12: a -> b, then jumps to c
+        registry
+            .register(12, &[Role::Read, Role::Write, Role::Read], move |values| {
+                count.fetch_add(1, Ordering::SeqCst);
+                Ok(Effect { writes: vec![values[0]], control: Control::Jump(values[1]) })
+            })
+            .unwrap();
+        let registry = Arc::new(registry);
+
+        let mut machine = Machine::with_extensions(&[10112, 7, 6, -1, 99], registry.clone());
+        assert_eq!(machine.run(), Err(MachineError::InvalidAddress { ip: 0, address: -1 }));
+        assert_eq!(machine.instruction_count(), 0);
+        assert_eq!(machine.memory().len(), 5);
+
+        let mut machine = Machine::with_extensions(&[10112, 7, 1, 4, 99], registry.clone());
+        let mut policy = Policy::new(Permissions::ALL);
+        policy.protect(0..4, Permissions::READ_EXECUTE);
+        machine.set_protection(policy);
+        assert_eq!(
+            machine.run(),
+            Err(MachineError::Protection { ip: 0, address: 1, access: Access::Write })
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(machine.instruction_count(), 0);
+
+        let mut machine = Machine::with_extensions(&[10112, 7, 6, 4, 99], registry);
+        assert_eq!(machine.run(), Ok(State::Halted));
+        assert_eq!((machine.memory()[6], machine.instruction_count()), (7, 2));
+    }
+
+    #[test]
+    fn test_unregistered() {
+        let mut machine = Machine::new(&[1110, 7, 3, 0, 99]);
+        assert_eq!(machine.run(), Err(MachineError::InvalidInstruction { ip: 0, instruction: 1110 }));
+    }
+
+    #[test]
+    fn test_instrumentation() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let mut registry = Registry::new();
+        let count = counter.clone();
+        registry
+            .register(42, &[Role::Read], move |values| {
+                count.fetch_add(1, Ordering::SeqCst);
+                Ok(Effect { writes: vec![], control: Control::Output(values[0] * 100) })
+            })
+            .unwrap();
+        let mut machine = Machine::with_extensions(&[142, 7, 104, 1, 42, 7, 99, 3], Arc::new(registry));
+        assert_eq!(machine.run(), Ok(State::Output(700)));
+        assert_eq!(machine.run_with(&[]), Ok(vec![1, 300]));
+        assert_eq!(counter.load(Ordering::SeqCst), 2);
+        assert_eq!(machine.instruction_count(), 4);
+    }
+}
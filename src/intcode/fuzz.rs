@@ -0,0 +1,307 @@
+//! Differential fuzzing between intcode engines.
+//!
+//! Random programs are built mostly from valid instructions with operands
+//! pointing into the program, so that they run for a while before failing.
+//! Every engine executes each program with the same inputs and budget, and the
+//! first program on which they disagree is shrunk to a minimal one.
+
+use std::any::Any;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+use super::conformance::{Engine, Failure, Outcome};
+
+/// xorshift64*, good enough for generating test programs.
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed.max(1) }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    pub fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+
+    /// A value in `low..high`, or `low` when the range is empty.
+    pub fn range(&mut self, low: i64, high: i64) -> i64 {
+        if high <= low {
+            return low;
+        }
+        low.wrapping_add(self.below(high.wrapping_sub(low) as u64) as i64)
+    }
+}
+
+const OP_CODES: [(i64, usize); 10] = [(1, 3), (2, 3), (3, 1), (4, 1), (5, 2), (6, 2), (7, 3), (8, 3), (9, 1), (99, 0)];
+
+/// A program of about `length` cells ending with a halt.
+pub fn program(rng: &mut Rng, length: usize) -> Vec<i64> {
+    let size = length as i64;
+    let mut program = Vec::new();
+    while program.len() < length {
+        if rng.below(10) == 0 {
+            program.push(rng.range(-10, size + 10));
+            continue;
+        }
+        let (op_code, arity) = OP_CODES[rng.below(OP_CODES.len() as u64) as usize];
+        // Now and then a mode which doesn't exist.
+        let modes: Vec<i64> = (0..arity).map(|_| if rng.below(30) == 0 { 3 } else { rng.range(0, 3) }).collect();
+        let instruction = modes.iter().rev().fold(0, |instruction, mode| instruction * 10 + mode) * 100 + op_code;
+        program.push(instruction);
+        for mode in modes {
+            program.push(match mode {
+                1 => rng.range(-5, size + 5),
+                2 => rng.range(-3, 4),
+                _ if rng.below(20) == 0 => -1,
+                _ => rng.range(0, size + 4),
+            });
+        }
+    }
+    program.push(99);
+    program
+}
+
+pub fn inputs(rng: &mut Rng) -> Vec<i64> {
+    (0..rng.below(5)).map(|_| rng.range(-5, 10)).collect()
+}
+
+fn agree(left: &Result<Outcome, Failure>, right: &Result<Outcome, Failure>, exact_errors: bool) -> bool {
+    match (left, right) {
+        (Ok(left), Ok(right)) => {
+            left.outputs == right.outputs
+                && match (&left.memory, &right.memory) {
+                    (Some(left), Some(right)) => left == right,
+                    _ => true,
+                }
+        }
+        (Err(Failure::Error(left)), Err(Failure::Error(right))) => !exact_errors || left == right,
+        _ => false,
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload.downcast_ref::<&str>().map_or("unknown panic", |message| message).to_owned(),
+    }
+}
+
+/// Runs one engine, turning a panic into `Failure::Panic` so that it is
+/// reported like any other divergence.
+fn execute(engine: &dyn Engine, program: &[i64], inputs: &[i64], budget: u64) -> Result<Outcome, Failure> {
+    panic::catch_unwind(AssertUnwindSafe(|| engine.execute(program, inputs, budget)))
+        .unwrap_or_else(|payload| Err(Failure::Panic(panic_message(payload))))
+}
+
+/// Results of every engine on one program, `None` when some engine ran out
+/// of budget, as engines may count instructions differently.
+fn results(engines: &[Box<dyn Engine>], program: &[i64], inputs: &[i64], budget: u64) -> Option<Vec<Result<Outcome, Failure>>> {
+    let results: Vec<_> = engines.iter().map(|engine| execute(engine.as_ref(), program, inputs, budget)).collect();
+    if results.contains(&Err(Failure::OutOfBudget)) {
+        return None;
+    }
+    Some(results)
+}
+
+pub fn diverges(engines: &[Box<dyn Engine>], program: &[i64], inputs: &[i64], budget: u64) -> bool {
+    let results = match results(engines, program, inputs, budget) {
+        Some(results) => results,
+        None => return false,
+    };
+    let exact = |index: usize| engines[index].exact_errors();
+    (1..results.len()).any(|index| !agree(&results[0], &results[index], exact(0) && exact(index)))
+}
+
+/// Shrinks a divergent program and its inputs while they still diverge: cuts
+/// the program short, drops runs of up to four cells and single inputs, then
+/// moves values towards zero.
+pub fn minimize(engines: &[Box<dyn Engine>], program: &[i64], inputs: &[i64], budget: u64) -> (Vec<i64>, Vec<i64>) {
+    let mut program = program.to_vec();
+    let mut inputs = inputs.to_vec();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for end in 0..program.len() {
+            let mut candidate = program[..end].to_vec();
+            candidate.push(99);
+            if candidate.len() < program.len() && diverges(engines, &candidate, &inputs, budget) {
+                program = candidate;
+                changed = true;
+                break;
+            }
+        }
+        for size in (1..=4).rev() {
+            for start in (0..program.len().saturating_sub(size - 1)).rev() {
+                if start + size > program.len() {
+                    continue;
+                }
+                let mut candidate = program.clone();
+                candidate.drain(start..start + size);
+                if diverges(engines, &candidate, &inputs, budget) {
+                    program = candidate;
+                    changed = true;
+                }
+            }
+        }
+        for index in (0..inputs.len()).rev() {
+            let mut candidate = inputs.clone();
+            candidate.remove(index);
+            if diverges(engines, &program, &candidate, budget) {
+                inputs = candidate;
+                changed = true;
+            }
+        }
+        for index in 0..program.len() {
+            while program[index] != 0 {
+                let mut candidate = program.clone();
+                candidate[index] /= 2;
+                if !diverges(engines, &candidate, &inputs, budget) {
+                    break;
+                }
+                program = candidate;
+                changed = true;
+            }
+        }
+    }
+    (program, inputs)
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Divergence {
+    pub program: Vec<i64>,
+    pub inputs: Vec<i64>,
+    /// The result of every engine, by name.
+    pub results: Vec<(&'static str, Result<Outcome, Failure>)>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let join = |values: &[i64]| values.iter().map(|value| value.to_string()).collect::<Vec<_>>().join(",");
+        writeln!(f, "program {}", join(&self.program))?;
+        writeln!(f, "input {}", join(&self.inputs))?;
+        for (name, result) in &self.results {
+            match result {
+                Ok(outcome) => writeln!(f, "{}: output {}", name, join(&outcome.outputs))?,
+                Err(Failure::Error(message)) => writeln!(f, "{}: error {}", name, message)?,
+                Err(Failure::OutOfBudget) => writeln!(f, "{}: out of budget", name)?,
+                Err(Failure::Panic(message)) => writeln!(f, "{}: panicked: {}", name, message)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Tries `iterations` random programs of up to `length` cells and returns
+/// the first divergence, minimised.
+pub fn fuzz(engines: &[Box<dyn Engine>], seed: u64, iterations: usize, length: usize, budget: u64) -> Option<Divergence> {
+    let mut rng = Rng::new(seed);
+    for _ in 0..iterations {
+        let length = 1 + rng.below(length as u64) as usize;
+        let program = program(&mut rng, length);
+        let inputs = inputs(&mut rng);
+        if diverges(engines, &program, &inputs, budget) {
+            let (program, inputs) = minimize(engines, &program, &inputs, budget);
+            let results = engines.iter().map(|engine| (engine.name(), execute(engine.as_ref(), &program, &inputs, budget))).collect();
+            return Some(Divergence { program, inputs, results });
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::conformance::{engines, Interpreter};
+    use super::super::Machine;
+    use super::*;
+
+    /// Drops the sign of negative outputs.
+    struct Broken;
+
+    impl Engine for Broken {
+        fn name(&self) -> &'static str {
+            "broken"
+        }
+
+        fn execute(&self, program: &[i64], inputs: &[i64], budget: u64) -> Result<Outcome, Failure> {
+            let mut outcome = Interpreter.execute(program, inputs, budget)?;
+            for output in &mut outcome.outputs {
+                *output = output.abs();
+            }
+            Ok(outcome)
+        }
+    }
+
+    /// Panics on programs producing any output.
+    struct Panicking;
+
+    impl Engine for Panicking {
+        fn name(&self) -> &'static str {
+            "panicking"
+        }
+
+        fn execute(&self, program: &[i64], inputs: &[i64], budget: u64) -> Result<Outcome, Failure> {
+            let outcome = Interpreter.execute(program, inputs, budget)?;
+            assert!(outcome.outputs.is_empty(), "output {:?}", outcome.outputs);
+            Ok(outcome)
+        }
+    }
+
+    #[test]
+    fn test_range() {
+        let mut rng = Rng::new(3);
+        assert_eq!(rng.range(4, 4), 4);
+        assert_eq!(rng.range(4, -4), 4);
+        assert!((i64::MIN..i64::MAX).contains(&rng.range(i64::MIN, i64::MAX)));
+    }
+
+    #[test]
+    fn test_programs_run() {
+        let mut rng = Rng::new(7);
+        let mut halted = 0;
+        for _ in 0..200 {
+            let program = program(&mut rng, 20);
+            let mut machine = Machine::new(&program);
+            machine.set_instruction_limit(1000);
+            if machine.run_with(&inputs(&mut rng)).is_ok() {
+                halted += 1;
+            }
+        }
+        assert!(halted > 20, "only {} programs halted", halted);
+    }
+
+    #[test]
+    fn test_engines_agree() {
+        let divergence = fuzz(&engines(), 2019, 300, 30, 1000);
+        assert!(divergence.is_none(), "{}", divergence.unwrap());
+    }
+
+    #[test]
+    fn test_panic() {
+        let engines: Vec<Box<dyn Engine>> = vec![Box::new(Interpreter), Box::new(Panicking)];
+        let divergence = fuzz(&engines, 1, 1000, 30, 1000).unwrap();
+        assert_eq!(divergence.program, vec![4, 0, 99]);
+        assert_eq!(divergence.results[1].1, Err(Failure::Panic("output [4]".to_owned())));
+    }
+
+    #[test]
+    fn test_minimize() {
+        let engines: Vec<Box<dyn Engine>> = vec![Box::new(Interpreter), Box::new(Broken)];
+        let divergence = fuzz(&engines, 1, 1000, 30, 1000).unwrap();
+        assert!(diverges(&engines, &divergence.program, &divergence.inputs, 1000));
+        assert!(divergence.program.len() <= 10, "{}", divergence);
+        for index in 0..divergence.program.len() {
+            let mut program = divergence.program.clone();
+            program.remove(index);
+            assert!(!diverges(&engines, &program, &divergence.inputs, 1000), "{}", divergence);
+        }
+    }
+}
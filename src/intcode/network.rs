@@ -0,0 +1,257 @@
+//! Networks of machines wired together as described in a text file.
+//!
+//! ```text
+//! # two amplifiers in a loop
+//! node A amplifier.txt
+//! node B amplifier.txt
+//! input A 9, 0
+//! input B 8
+//! edge A -> B
+//! edge B -> A
+//! output B
+//! ```
+//!
+//! Every `node` runs its own copy of a program file, resolved relative to the
+//! description; a topology built in code may also hand a node its program
+//! directly. A node first reads its `input` values, then whatever the nodes
+//! with an `edge` into it send; every output goes to all of its edges. The
+//! result of the network is everything the `output` node sent.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use super::async_driver::{self, AsyncMachine, Executor, Sender};
+use super::{parse_numbers, read_program, Machine};
+
+/// Where the program of a node comes from.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Source {
+    /// Resolved by the `programs` function given to `run`, a file name for
+    /// `run_files` and in a description.
+    Name(String),
+    /// The program itself, shared by every node running it.
+    Code(Arc<Vec<i64>>),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Node {
+    pub name: String,
+    pub program: Source,
+    pub inputs: Vec<i64>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Topology {
+    pub nodes: Vec<Node>,
+    /// Indices into `nodes`, from sender to receiver.
+    pub edges: Vec<(usize, usize)>,
+    pub output: usize,
+}
+
+/// The first word of `text` and the rest after it.
+fn split_word(text: &str) -> (&str, &str) {
+    let text = text.trim();
+    match text.find(char::is_whitespace) {
+        Some(end) => (&text[..end], text[end..].trim_start()),
+        None => (text, ""),
+    }
+}
+
+impl Topology {
+    fn find(&self, name: &str) -> Result<usize, String> {
+        self.nodes.iter().position(|node| node.name == name).ok_or_else(|| format!("unknown node {:?}", name))
+    }
+
+    pub fn parse(text: &str) -> Result<Topology, String> {
+        let mut topology = Topology::default();
+        let mut output = None;
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| format!("line {}: {}", index + 1, message);
+            let (key, rest) = split_word(line);
+            if key == "edge" {
+                let (from, to) = rest.split_once("->").ok_or_else(|| error("expected -> in edge".to_owned()))?;
+                let edge = (topology.find(from.trim()).map_err(error)?, topology.find(to.trim()).map_err(error)?);
+                topology.edges.push(edge);
+                continue;
+            }
+            let (name, rest) = split_word(rest);
+            if name.is_empty() {
+                return Err(error(format!("expected a node after {}", key)));
+            }
+            match key {
+                "node" if topology.find(name).is_ok() => return Err(error(format!("duplicate node {:?}", name))),
+                "node" if rest.is_empty() => return Err(error(format!("expected a program for {:?}", name))),
+                "node" => {
+                    let program = Source::Name(rest.to_owned());
+                    topology.nodes.push(Node { name: name.to_owned(), program, inputs: Vec::new() });
+                }
+                "input" => {
+                    let node = topology.find(name).map_err(error)?;
+                    let inputs = parse_numbers(rest).map_err(error)?;
+                    topology.nodes[node].inputs.extend(inputs);
+                }
+                "output" => output = Some(topology.find(name).map_err(error)?),
+                _ => return Err(error(format!("unknown key {:?}", key))),
+            }
+        }
+        topology.output = output.ok_or_else(|| "expected an output node".to_owned())?;
+        Ok(topology)
+    }
+
+    /// Parses `filename`, making program paths relative to its directory.
+    pub fn load(filename: &str) -> Result<Topology, String> {
+        let contents = fs::read_to_string(filename).map_err(|error| format!("{}: {}", filename, error))?;
+        let mut topology = Topology::parse(&contents).map_err(|error| format!("{}: {}", filename, error))?;
+        let directory = Path::new(filename).parent().unwrap_or_else(|| Path::new(""));
+        for node in &mut topology.nodes {
+            if let Source::Name(program) = &mut node.program {
+                *program = directory.join(&*program).to_string_lossy().into_owned();
+            }
+        }
+        Ok(topology)
+    }
+}
+
+/// Runs the network until every machine halts, `programs` turns the program
+/// name of a node into its code. Fails if a machine fails or the network gets
+/// stuck waiting for input.
+pub fn run(topology: &Topology, programs: &dyn Fn(&str) -> Result<Vec<i64>, String>) -> Result<Vec<i64>, String> {
+    let mut named: HashMap<&str, Machine> = HashMap::new();
+    let mut images = Vec::new();
+    for node in &topology.nodes {
+        images.push(match &node.program {
+            Source::Name(name) => {
+                if !named.contains_key(name.as_str()) {
+                    named.insert(name, Machine::new(&programs(name)?));
+                }
+                named[name.as_str()].clone()
+            }
+            Source::Code(code) => Machine::from_image(code.clone()),
+        });
+    }
+
+    let (senders, receivers): (Vec<Sender>, Vec<_>) = topology.nodes.iter().map(|_| async_driver::channel()).unzip();
+    for (sender, node) in senders.iter().zip(&topology.nodes) {
+        for input in &node.inputs {
+            sender.send(*input);
+        }
+    }
+
+    let outputs = Rc::new(RefCell::new(Vec::new()));
+    let failure = Rc::new(RefCell::new(None));
+    let mut executor = Executor::new();
+    for (index, ((node, image), receiver)) in topology.nodes.iter().zip(images).zip(receivers).enumerate() {
+        let machine = AsyncMachine::new(image, receiver);
+        let targets: Vec<Sender> =
+            topology.edges.iter().filter(|(from, _)| *from == index).map(|(_, to)| senders[*to].clone()).collect();
+        let outputs = if index == topology.output { Some(outputs.clone()) } else { None };
+        let failure = failure.clone();
+        let name = node.name.clone();
+        executor.spawn(async move {
+            let mut values = machine.outputs();
+            while let Some(value) = values.next().await {
+                match value {
+                    Ok(value) => {
+                        for target in &targets {
+                            target.send(value);
+                        }
+                        if let Some(outputs) = &outputs {
+                            outputs.borrow_mut().push(value);
+                        }
+                    }
+                    Err(error) => {
+                        failure.borrow_mut().get_or_insert_with(|| format!("node {}: {}", name, error));
+                        return;
+                    }
+                }
+            }
+        });
+    }
+    drop(senders);
+
+    let blocked = executor.run();
+    if let Some(failure) = failure.borrow_mut().take() {
+        return Err(failure);
+    }
+    if blocked > 0 {
+        return Err(format!("{} nodes are stuck waiting for input", blocked));
+    }
+    let outputs = outputs.borrow().clone();
+    Ok(outputs)
+}
+
+/// Runs the network reading every program from its file.
+pub fn run_files(topology: &Topology) -> Result<Vec<i64>, String> {
+    run(topology, &read_program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn programs(name: &str) -> Result<Vec<i64>, String> {
+        match name {
+            "sum" => Ok(crate::intcode![in x; in y; add x, y -> x; out x; halt; x: 0; y: 0]),
+            "double" => Ok(crate::intcode![in x; mul x, #2 -> x; out x; halt; x: 0]),
+            _ => Err(format!("no program {}", name)),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let topology = Topology::parse("node A double\nnode B double\ninput A 1, 2\n\n# done\nedge A -> B\noutput B").unwrap();
+        assert_eq!(topology.nodes[0].inputs, vec![1, 2]);
+        assert_eq!(topology.edges, vec![(0, 1)]);
+        assert_eq!(topology.output, 1);
+        assert_eq!(Topology::parse("node A double"), Err("expected an output node".to_owned()));
+        assert_eq!(Topology::parse("node A double\nedge A B"), Err("line 2: expected -> in edge".to_owned()));
+        assert_eq!(Topology::parse("node A double\ninput C 1"), Err("line 2: unknown node \"C\"".to_owned()));
+        assert_eq!(Topology::parse("node A x\nnode A y"), Err("line 2: duplicate node \"A\"".to_owned()));
+        assert_eq!(Topology::parse("node A x\ninput"), Err("line 2: expected a node after input".to_owned()));
+    }
+
+    #[test]
+    fn test_parse_spacing() {
+        let text = "node\tA   double\nnode B  double\ninput  A 1,2\nedge A->B\nedge   B  ->A\noutput  B ";
+        let topology = Topology::parse(text).unwrap();
+        let program = Source::Name("double".to_owned());
+        assert_eq!(topology.nodes[0], Node { name: "A".to_owned(), program, inputs: vec![1, 2] });
+        assert_eq!(topology.edges, vec![(0, 1), (1, 0)]);
+        assert_eq!(topology.output, 1);
+        assert_eq!(Topology::parse("node A x\nedge A->"), Err("line 2: unknown node \"\"".to_owned()));
+    }
+
+    #[test]
+    fn test_fan_out() {
+        let text = "node A double\nnode B double\nnode C double\nnode D sum\n\
+                    input A 3\nedge A -> B\nedge A -> C\nedge B -> D\nedge C -> D\noutput D";
+        let topology = Topology::parse(text).unwrap();
+        assert_eq!(run(&topology, &programs), Ok(vec![24]));
+    }
+
+    #[test]
+    fn test_failure() {
+        let topology = Topology::parse("node A double\noutput A").unwrap();
+        assert_eq!(run(&topology, &programs), Err("node A: input exhausted at 0".to_owned()));
+        let topology = Topology::parse("node A double\nnode B double\nedge A -> B\nedge B -> A\noutput B").unwrap();
+        assert_eq!(run(&topology, &programs), Err("2 nodes are stuck waiting for input".to_owned()));
+        let topology = Topology::parse("node A missing\noutput A").unwrap();
+        assert_eq!(run(&topology, &programs), Err("no program missing".to_owned()));
+    }
+
+    #[test]
+    fn test_code() {
+        let mut topology = Topology::parse("node A double\nnode B missing\ninput A 5\nedge A -> B\noutput B").unwrap();
+        topology.nodes[1].program = Source::Code(Arc::new(programs("sum").unwrap()));
+        topology.nodes[1].inputs.push(1);
+        assert_eq!(run(&topology, &programs), Ok(vec![11]));
+    }
+}
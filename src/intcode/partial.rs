@@ -0,0 +1,322 @@
+//! Partial evaluation of intcode programs against a known input prefix.
+//!
+//! The program is executed until it asks for an input beyond the prefix.
+//! Whatever is reachable from that point is then laid out again as a new,
+//! usually much smaller program: unreachable code is dropped, data cells are
+//! packed next to the code using them and branches whose condition is already
+//! known are folded. Outputs produced while consuming the prefix are emitted
+//! first, so the residual program behaves exactly like the original one.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::{instruction_to_op_code, Error, Machine, Mode, OpCode, State};
+
+/// A program specialised for an input prefix.
+#[derive(Debug, Clone)]
+pub enum Specialized {
+    /// Relocated program without the code and data made dead by the prefix.
+    Residual(Vec<i64>),
+    /// State after the prefix, for programs which are unsafe to relocate,
+    /// e.g. self-modifying ones or those with computed jumps.
+    Snapshot { machine: Machine, outputs: Vec<i64> },
+}
+
+impl Specialized {
+    /// Runs a fresh copy of the specialised program with the rest of the inputs.
+    pub fn run_with(&self, inputs: &[i64]) -> Result<Vec<i64>, Error> {
+        match self {
+            Specialized::Residual(program) => Machine::new(program).run_with(inputs),
+            Specialized::Snapshot { machine, outputs } => {
+                let mut machine = machine.clone();
+                let mut all_outputs = outputs.clone();
+                all_outputs.extend(machine.run_with(inputs)?);
+                Ok(all_outputs)
+            }
+        }
+    }
+}
+
+/// Specialises `program` for the fixed input `prefix`.
+pub fn specialize(program: &[i64], prefix: &[i64]) -> Result<Specialized, Error> {
+    let mut machine = Machine::new(program);
+    for input in prefix {
+        machine.push_input(*input);
+    }
+    let mut outputs = Vec::new();
+    while let State::Output(value) = machine.run()? {
+        outputs.push(value);
+    }
+    if machine.is_halted() {
+        let mut residual = emit_outputs(&outputs);
+        residual.push(99);
+        return Ok(Specialized::Residual(residual));
+    }
+    match relocate(machine.memory(), machine.ip(), &outputs) {
+        Some(residual) => Ok(Specialized::Residual(residual)),
+        None => Ok(Specialized::Snapshot { machine, outputs }),
+    }
+}
+
+fn emit_outputs(outputs: &[i64]) -> Vec<i64> {
+    outputs.iter().flat_map(|value| vec![104, *value]).collect()
+}
+
+/// What a reachable conditional jump does once its condition is known.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Branch {
+    Unknown,
+    Taken,
+    NotTaken,
+}
+
+#[derive(Default)]
+struct Analysis {
+    instructions: BTreeMap<usize, (OpCode, Branch)>,
+    reads: BTreeSet<usize>,
+    writes: BTreeSet<usize>,
+}
+
+/// Collects the code reachable from `entry` and the cells it reads and writes.
+///
+/// A condition read from memory is only folded when `written` is given and
+/// does not contain its cell. Returns `None` when the code cannot be analysed
+/// statically.
+fn analyse(memory: &[i64], entry: usize, written: Option<&BTreeSet<usize>>) -> Option<Analysis> {
+    let address = |value: i64| {
+        if value >= 0 && (value as usize) < memory.len() { Some(value as usize) } else { None }
+    };
+    let mut analysis = Analysis::default();
+    let mut queue = vec![entry];
+    while let Some(ip) = queue.pop() {
+        if analysis.instructions.contains_key(&ip) {
+            continue;
+        }
+        let operation_code = instruction_to_op_code(*memory.get(ip)?)?;
+        let length = operation_code.length();
+        if ip + length > memory.len() {
+            return None;
+        }
+        // Relative addresses are only known at run time.
+        if operation_code.modes().contains(&Mode::Relative) {
+            return None;
+        }
+        let mut branch = Branch::Unknown;
+        match operation_code {
+            OpCode::Add(mode_1, mode_2, _)
+            | OpCode::Multiply(mode_1, mode_2, _)
+            | OpCode::LessThan(mode_1, mode_2, _)
+            | OpCode::Equals(mode_1, mode_2, _) => {
+                for (mode, offset) in &[(mode_1, 1), (mode_2, 2)] {
+                    if *mode == Mode::Position {
+                        analysis.reads.insert(address(memory[ip + offset])?);
+                    }
+                }
+                analysis.writes.insert(address(memory[ip + 3])?);
+                queue.push(ip + length);
+            }
+            OpCode::Save(_) => {
+                analysis.writes.insert(address(memory[ip + 1])?);
+                queue.push(ip + length);
+            }
+            OpCode::Output(mode) | OpCode::AdjustRelativeBase(mode) => {
+                if mode == Mode::Position {
+                    analysis.reads.insert(address(memory[ip + 1])?);
+                }
+                queue.push(ip + length);
+            }
+            OpCode::JumpIfTrue(mode_1, Mode::Immediate) | OpCode::JumpIfFalse(mode_1, Mode::Immediate) => {
+                let target = address(memory[ip + 2])?;
+                let condition = match mode_1 {
+                    Mode::Immediate => Some(memory[ip + 1]),
+                    Mode::Position | Mode::Relative => {
+                        let cell = address(memory[ip + 1])?;
+                        analysis.reads.insert(cell);
+                        match written {
+                            Some(written) if !written.contains(&cell) => Some(memory[cell]),
+                            _ => None,
+                        }
+                    }
+                };
+                let jump_if = matches!(operation_code, OpCode::JumpIfTrue(_, _));
+                branch = match condition {
+                    Some(condition) if (condition != 0) == jump_if => Branch::Taken,
+                    Some(_) => Branch::NotTaken,
+                    None => Branch::Unknown,
+                };
+                if branch != Branch::NotTaken {
+                    queue.push(target);
+                }
+                if branch != Branch::Taken {
+                    queue.push(ip + length);
+                }
+            }
+            // A jump through memory can land anywhere.
+            OpCode::JumpIfTrue(_, _) | OpCode::JumpIfFalse(_, _) => return None,
+            OpCode::Halt => (),
+        }
+        analysis.instructions.insert(ip, (operation_code, branch));
+    }
+    Some(analysis)
+}
+
+/// Lays out the code reachable from `entry` as a new program starting at zero.
+fn relocate(memory: &[i64], entry: usize, outputs: &[i64]) -> Option<Vec<i64>> {
+    // The first pass over-approximates the written cells, so every cell it
+    // never writes keeps its current value and can be used to fold branches.
+    let conservative = analyse(memory, entry, None)?;
+    let analysis = analyse(memory, entry, Some(&conservative.writes))?;
+
+    let mut code = BTreeSet::new();
+    for (ip, (operation_code, _)) in &analysis.instructions {
+        for cell in *ip..*ip + operation_code.length() {
+            // Overlapping instructions can't be relocated independently.
+            if !code.insert(cell) {
+                return None;
+            }
+        }
+    }
+    // Data living inside code would change when operands are rewritten.
+    if analysis.reads.iter().chain(&analysis.writes).any(|cell| code.contains(cell)) {
+        return None;
+    }
+
+    let live: BTreeSet<usize> = code.iter().chain(&analysis.reads).chain(&analysis.writes).cloned().collect();
+    let mut residual = emit_outputs(outputs);
+    let needs_jump = live.iter().next() != Some(&entry);
+    let base = residual.len() + if needs_jump { 3 } else { 0 };
+    let new_address: BTreeMap<usize, i64> = live
+        .iter()
+        .enumerate()
+        .map(|(index, cell)| (*cell, (base + index) as i64))
+        .collect();
+    if needs_jump {
+        residual.extend(&[1105, 1, new_address[&entry]]);
+    }
+
+    let start = residual.len();
+    residual.extend(live.iter().map(|cell| memory[*cell]));
+    for (ip, (operation_code, branch)) in &analysis.instructions {
+        let at = start + (new_address[ip] as usize - base);
+        let remap = |offset: usize| new_address[&(memory[ip + offset] as usize)];
+        match operation_code {
+            OpCode::Add(mode_1, mode_2, _)
+            | OpCode::Multiply(mode_1, mode_2, _)
+            | OpCode::LessThan(mode_1, mode_2, _)
+            | OpCode::Equals(mode_1, mode_2, _) => {
+                for (mode, offset) in &[(mode_1, 1), (mode_2, 2)] {
+                    if **mode == Mode::Position {
+                        residual[at + offset] = remap(*offset);
+                    }
+                }
+                residual[at + 3] = remap(3);
+            }
+            OpCode::Save(_) | OpCode::Output(Mode::Position) | OpCode::AdjustRelativeBase(Mode::Position) => {
+                residual[at + 1] = remap(1)
+            }
+            OpCode::JumpIfTrue(mode, _) | OpCode::JumpIfFalse(mode, _) => match branch {
+                Branch::Taken => residual[at..at + 3].copy_from_slice(&[1105, 1, remap(2)]),
+                Branch::NotTaken => residual[at..at + 3].copy_from_slice(&[1106, 1, 0]),
+                Branch::Unknown => {
+                    if *mode == Mode::Position {
+                        residual[at + 1] = remap(1);
+                    }
+                    residual[at + 2] = remap(2);
+                }
+            },
+            OpCode::Output(_) | OpCode::AdjustRelativeBase(_) | OpCode::Halt => (),
+        }
+    }
+    Some(residual)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::intcode;
+
+    /// Outputs produced for `inputs`, stopping when the machine blocks or halts.
+    fn outputs_until_blocked(mut machine: Machine, inputs: &[i64]) -> Vec<i64> {
+        for input in inputs {
+            machine.push_input(*input);
+        }
+        let mut outputs = Vec::new();
+        while let Ok(State::Output(value)) = machine.run() {
+            outputs.push(value);
+        }
+        outputs
+    }
+
+    #[test]
+    fn test_amplifier_example() {
+        let program = vec![3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0];
+        let residual = match specialize(&program, &[4]).unwrap() {
+            Specialized::Residual(residual) => residual,
+            Specialized::Snapshot { .. } => panic!("the program should be relocatable"),
+        };
+        assert_eq!(residual, vec![3, 14, 1002, 14, 10, 14, 1, 14, 13, 13, 4, 13, 99, 4, 0]);
+        assert_eq!(Machine::new(&residual).run_with(&[3]), Ok(vec![34]));
+    }
+
+    #[test]
+    fn test_prefix_outputs() {
+        let program = vec![3, 9, 4, 9, 3, 10, 4, 10, 99, 0, 0];
+        let specialized = specialize(&program, &[5]).unwrap();
+        assert_eq!(specialized.run_with(&[6]), Ok(vec![5, 6]));
+        if let Specialized::Residual(residual) = specialized {
+            assert_eq!(&residual[..2], &[104, 5]);
+        }
+    }
+
+    #[test]
+    fn test_halted() {
+        let program = vec![3, 7, 4, 7, 4, 7, 99, 0];
+        let specialized = specialize(&program, &[8]).unwrap();
+        match specialized {
+            Specialized::Residual(residual) => assert_eq!(residual, vec![104, 8, 104, 8, 99]),
+            Specialized::Snapshot { .. } => panic!("a halted program is always relocatable"),
+        }
+    }
+
+    #[test]
+    fn test_folded_branch() {
+        // Jumps over both immediate outputs because cell 14 is never written.
+        let program = vec![3, 15, 1005, 14, 9, 104, 1, 104, 2, 4, 15, 99, 0, 0, 1, 0];
+        let specialized = specialize(&program, &[]).unwrap();
+        let residual = match &specialized {
+            Specialized::Residual(residual) => residual.clone(),
+            Specialized::Snapshot { .. } => panic!("the program should be relocatable"),
+        };
+        assert_eq!(residual, vec![3, 9, 1105, 1, 5, 4, 9, 99, 1, 0]);
+        assert_eq!(specialized.run_with(&[7]), Machine::new(&program).run_with(&[7]));
+    }
+
+    #[test]
+    fn test_self_modifying() {
+        // Patches its own output instruction into immediate mode.
+        let program = vec![3, 11, 1001, 6, 100, 6, 4, 11, 99, 0, 0, 0];
+        let specialized = specialize(&program, &[]).unwrap();
+        assert!(matches!(specialized, Specialized::Snapshot { .. }));
+        assert_eq!(specialized.run_with(&[7]), Ok(vec![11]));
+    }
+
+    #[test]
+    fn test_day_7_amplifiers() {
+        let program = intcode::read_input("input/day7.txt");
+        for phase in 0..10 {
+            let specialized = specialize(&program, &[phase]).unwrap();
+            let residual = match specialized {
+                Specialized::Residual(residual) => residual,
+                Specialized::Snapshot { .. } => panic!("phase {} should be relocatable", phase),
+            };
+            assert!(residual.len() < program.len() / 2);
+            let signals = [0, 1, 17, 123_456];
+            let mut original = Machine::new(&program);
+            original.push_input(phase);
+            assert_eq!(
+                outputs_until_blocked(Machine::new(&residual), &signals),
+                outputs_until_blocked(original, &signals),
+            );
+        }
+    }
+}
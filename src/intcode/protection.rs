@@ -0,0 +1,150 @@
+//! Memory regions with access permissions.
+//!
+//! Fetching an instruction and its parameters is an execute access, following
+//! a position or relative parameter is a read and storing a result is a write.
+//! When regions overlap the one added last wins, every other address gets the
+//! default permissions of the policy.
+
+use std::fmt;
+use std::ops::Range;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Access::Read => write!(f, "read"),
+            Access::Write => write!(f, "write"),
+            Access::Execute => write!(f, "execute"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Permissions {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl Permissions {
+    pub const ALL: Permissions = Permissions { read: true, write: true, execute: true };
+    pub const READ_ONLY: Permissions = Permissions { read: true, write: false, execute: false };
+    pub const READ_WRITE: Permissions = Permissions { read: true, write: true, execute: false };
+    pub const READ_EXECUTE: Permissions = Permissions { read: true, write: false, execute: true };
+    pub const EXECUTE_ONLY: Permissions = Permissions { read: false, write: false, execute: true };
+    pub const NONE: Permissions = Permissions { read: false, write: false, execute: false };
+
+    pub fn allows(self, access: Access) -> bool {
+        match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+            Access::Execute => self.execute,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Region {
+    pub range: Range<usize>,
+    pub permissions: Permissions,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Policy {
+    default: Permissions,
+    regions: Vec<Region>,
+}
+
+impl Policy {
+    pub fn new(default: Permissions) -> Policy {
+        Policy { default, regions: Vec::new() }
+    }
+
+    /// Code which can run but can't be patched by itself, the rest of memory
+    /// is data and can't be executed.
+    pub fn read_only_code(length: usize) -> Policy {
+        let mut policy = Policy::new(Permissions::READ_WRITE);
+        policy.protect(0..length, Permissions::READ_EXECUTE);
+        policy
+    }
+
+    pub fn protect(&mut self, range: Range<usize>, permissions: Permissions) {
+        self.regions.push(Region { range, permissions })
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    pub fn permissions(&self, address: usize) -> Permissions {
+        self.regions
+            .iter()
+            .rev()
+            .find(|region| region.range.contains(&address))
+            .map_or(self.default, |region| region.permissions)
+    }
+
+    pub fn allows(&self, address: usize, access: Access) -> bool {
+        self.permissions(address).allows(access)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Error, Machine};
+    use super::*;
+
+    #[test]
+    fn test_permissions() {
+        let mut policy = Policy::new(Permissions::ALL);
+        policy.protect(0..10, Permissions::READ_ONLY);
+        policy.protect(5..6, Permissions::NONE);
+        assert!(policy.allows(4, Access::Read));
+        assert!(!policy.allows(4, Access::Write));
+        assert!(!policy.allows(5, Access::Read));
+        assert!(policy.allows(10, Access::Execute));
+    }
+
+    #[test]
+    fn test_self_modifying_code() {
+        // Patches the parameter of its own output.
+        let program = vec![1101, 0, 9, 5, 104, 7, 99];
+        assert_eq!(Machine::new(&program).run_with(&[]), Ok(vec![9]));
+
+        let mut machine = Machine::new(&program);
+        machine.set_protection(Policy::read_only_code(program.len()));
+        assert_eq!(
+            machine.run_with(&[]),
+            Err(Error::Protection { ip: 0, address: 5, access: Access::Write })
+        );
+    }
+
+    #[test]
+    fn test_execute_data() {
+        let mut machine = Machine::new(&[1105, 1, 4, 99, 104, 1, 99]);
+        let mut policy = Policy::new(Permissions::ALL);
+        policy.protect(4..7, Permissions::READ_WRITE);
+        machine.set_protection(policy);
+        assert_eq!(machine.run(), Err(Error::Protection { ip: 4, address: 4, access: Access::Execute }));
+    }
+
+    #[test]
+    fn test_execute_only() {
+        let program = vec![4, 0, 104, 5, 99];
+        let mut machine = Machine::new(&program);
+        let mut policy = Policy::new(Permissions::NONE);
+        policy.protect(0..program.len(), Permissions::EXECUTE_ONLY);
+        machine.set_protection(policy);
+        assert_eq!(machine.run(), Err(Error::Protection { ip: 0, address: 0, access: Access::Read }));
+
+        let mut machine = Machine::new(&program[2..]);
+        machine.set_protection(Policy::new(Permissions::EXECUTE_ONLY));
+        assert_eq!(machine.run_with(&[]), Ok(vec![5]));
+    }
+}
@@ -0,0 +1,418 @@
+//! Recording and deterministic replay of intcode sessions.
+//!
+//! A recording keeps every input fed to the machine, tagged with the
+//! instruction count at which the program consumed it, together with every
+//! output in the order they happened. Replaying feeds the same inputs at the
+//! same points and can check that the program still produces the same outputs.
+
+use std::collections::VecDeque;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::mem;
+use std::str::FromStr;
+
+use super::{Machine, State};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Event {
+    Input { instruction: u64, value: i64 },
+    Output(i64),
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Recording {
+    pub events: Vec<Event>,
+}
+
+impl Recording {
+    pub fn inputs(&self) -> impl Iterator<Item = (u64, i64)> + '_ {
+        self.events.iter().filter_map(|event| match event {
+            Event::Input { instruction, value } => Some((*instruction, *value)),
+            Event::Output(_) => None,
+        })
+    }
+
+    pub fn outputs(&self) -> impl Iterator<Item = i64> + '_ {
+        self.events.iter().filter_map(|event| match event {
+            Event::Output(value) => Some(*value),
+            Event::Input { .. } => None,
+        })
+    }
+
+    pub fn save(&self, filename: &str) -> io::Result<()> {
+        fs::write(filename, self.to_string())
+    }
+
+    pub fn load(filename: &str) -> io::Result<Recording> {
+        let contents = fs::read_to_string(filename)?;
+        contents
+            .parse()
+            .map_err(|line| io::Error::new(io::ErrorKind::InvalidData, format!("cannot parse recording line: {}", line)))
+    }
+}
+
+/// One event per line: `input <instruction> <value>` or `output <value>`.
+impl fmt::Display for Recording {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for event in &self.events {
+            match event {
+                Event::Input { instruction, value } => writeln!(f, "input {} {}", instruction, value)?,
+                Event::Output(value) => writeln!(f, "output {}", value)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Recording {
+    /// The offending line.
+    type Err = String;
+
+    fn from_str(contents: &str) -> Result<Recording, String> {
+        let mut events = Vec::new();
+        for line in contents.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let event = match words.as_slice() {
+                ["input", instruction, value] => match (instruction.parse(), value.parse()) {
+                    (Ok(instruction), Ok(value)) => Event::Input { instruction, value },
+                    _ => return Err(line.to_owned()),
+                },
+                ["output", value] => match value.parse() {
+                    Ok(value) => Event::Output(value),
+                    Err(_) => return Err(line.to_owned()),
+                },
+                _ => return Err(line.to_owned()),
+            };
+            events.push(event);
+        }
+        Ok(Recording { events })
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Error {
+    Machine(super::Error),
+    /// The program asked for recorded input `index` at a different point.
+    InputMismatch { index: usize, expected_instruction: u64, actual_instruction: u64 },
+    OutputMismatch { index: usize, expected: Option<i64>, actual: i64 },
+    /// The program halted before producing recorded output `index`.
+    MissingOutput { index: usize, expected: i64 },
+    /// The program halted before reading recorded input `index`.
+    UnusedInput { index: usize, expected_instruction: u64 },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Machine(error) => write!(f, "{}", error),
+            Error::InputMismatch { index, expected_instruction, actual_instruction } => write!(
+                f,
+                "input {} was recorded at instruction {} but requested at {}",
+                index, expected_instruction, actual_instruction
+            ),
+            Error::OutputMismatch { index, expected: Some(expected), actual } => {
+                write!(f, "output {} is {}, recorded {}", index, actual, expected)
+            }
+            Error::OutputMismatch { index, expected: None, actual } => {
+                write!(f, "output {} is {}, nothing was recorded", index, actual)
+            }
+            Error::MissingOutput { index, expected } => {
+                write!(f, "halted before output {}, recorded {}", index, expected)
+            }
+            Error::UnusedInput { index, expected_instruction } => {
+                write!(f, "halted before input {}, recorded at instruction {}", index, expected_instruction)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<super::Error> for Error {
+    fn from(error: super::Error) -> Error {
+        Error::Machine(error)
+    }
+}
+
+/// Wraps a machine and records the session while it runs.
+///
+/// Inputs are queued and handed to the machine one at a time when it asks
+/// for them, so each one is tagged with the exact instruction consuming it.
+/// Inputs already queued on the machine are taken over and recorded the same
+/// way.
+pub struct Recorder {
+    machine: Machine,
+    pending: VecDeque<i64>,
+    recording: Recording,
+}
+
+impl Recorder {
+    pub fn new(mut machine: Machine) -> Recorder {
+        Recorder {
+            pending: mem::take(&mut machine.inputs),
+            machine,
+            recording: Recording::default(),
+        }
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    pub fn into_recording(self) -> Recording {
+        self.recording
+    }
+
+    pub fn push_input(&mut self, value: i64) {
+        self.pending.push_back(value)
+    }
+
+    pub fn run(&mut self) -> Result<State, super::Error> {
+        loop {
+            match self.machine.run()? {
+                State::AwaitingInput => match self.pending.pop_front() {
+                    Some(value) => {
+                        let instruction = self.machine.instruction_count();
+                        self.recording.events.push(Event::Input { instruction, value });
+                        self.machine.push_input(value);
+                    }
+                    None => return Ok(State::AwaitingInput),
+                },
+                State::Output(value) => {
+                    self.recording.events.push(Event::Output(value));
+                    return Ok(State::Output(value));
+                }
+                State::Halted => return Ok(State::Halted),
+            }
+        }
+    }
+}
+
+/// Replays a recording against a machine.
+///
+/// Once the recorded inputs run out the machine reports `AwaitingInput`
+/// again, so a replayed session can be continued interactively.
+/// With `verify_outputs`, every output must match the recording and halting
+/// before all recorded inputs and outputs are used up is a divergence.
+pub struct Replay {
+    machine: Machine,
+    inputs: Vec<(u64, i64)>,
+    outputs: Vec<i64>,
+    verify_outputs: bool,
+    consumed: usize,
+    produced: usize,
+}
+
+impl Replay {
+    pub fn new(machine: Machine, recording: &Recording, verify_outputs: bool) -> Replay {
+        Replay {
+            machine,
+            inputs: recording.inputs().collect(),
+            outputs: recording.outputs().collect(),
+            verify_outputs,
+            consumed: 0,
+            produced: 0,
+        }
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    pub fn into_machine(self) -> Machine {
+        self.machine
+    }
+
+    /// True when every recorded input has been fed to the machine.
+    pub fn is_finished(&self) -> bool {
+        self.consumed == self.inputs.len()
+    }
+
+    pub fn run(&mut self) -> Result<State, Error> {
+        loop {
+            match self.machine.run()? {
+                State::AwaitingInput => {
+                    let (expected_instruction, value) = match self.inputs.get(self.consumed) {
+                        Some(input) => *input,
+                        None => return Ok(State::AwaitingInput),
+                    };
+                    let actual_instruction = self.machine.instruction_count();
+                    if actual_instruction != expected_instruction {
+                        return Err(Error::InputMismatch {
+                            index: self.consumed,
+                            expected_instruction,
+                            actual_instruction,
+                        });
+                    }
+                    self.consumed += 1;
+                    self.machine.push_input(value);
+                }
+                State::Output(actual) => {
+                    let index = self.produced;
+                    self.produced += 1;
+                    let expected = self.outputs.get(index).cloned();
+                    if self.verify_outputs && expected != Some(actual) {
+                        return Err(Error::OutputMismatch { index, expected, actual });
+                    }
+                    return Ok(State::Output(actual));
+                }
+                State::Halted if !self.verify_outputs => return Ok(State::Halted),
+                State::Halted => {
+                    if let Some(expected) = self.outputs.get(self.produced) {
+                        return Err(Error::MissingOutput { index: self.produced, expected: *expected });
+                    }
+                    if let Some((expected_instruction, _)) = self.inputs.get(self.consumed) {
+                        let expected_instruction = *expected_instruction;
+                        return Err(Error::UnusedInput { index: self.consumed, expected_instruction });
+                    }
+                    return Ok(State::Halted);
+                }
+            }
+        }
+    }
+
+    /// Replays the whole recording and runs the machine until it halts.
+    pub fn run_to_end(&mut self) -> Result<Vec<i64>, Error> {
+        let mut outputs = Vec::new();
+        loop {
+            match self.run()? {
+                State::Output(value) => outputs.push(value),
+                State::AwaitingInput => {
+                    return Err(Error::Machine(super::Error::EndOfInput { ip: self.machine.ip() }))
+                }
+                State::Halted => return Ok(outputs),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Echoes inputs doubled until it reads a zero.
+    const DOUBLER: [i64; 16] = [3, 15, 1006, 15, 14, 1002, 15, 2, 15, 4, 15, 1105, 1, 0, 99, 0];
+
+    fn doubler() -> Machine {
+        Machine::new(&DOUBLER)
+    }
+
+    fn record(inputs: &[i64]) -> Recording {
+        let mut recorder = Recorder::new(doubler());
+        for input in inputs {
+            recorder.push_input(*input);
+        }
+        while let State::Output(_) = recorder.run().unwrap() {}
+        recorder.into_recording()
+    }
+
+    #[test]
+    fn test_record() {
+        let recording = record(&[3, 5, 0]);
+        assert_eq!(recording.events, vec![
+            Event::Input { instruction: 0, value: 3 },
+            Event::Output(6),
+            Event::Input { instruction: 5, value: 5 },
+            Event::Output(10),
+            Event::Input { instruction: 10, value: 0 },
+        ]);
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        let recording = record(&[3, 5, 0]);
+        assert_eq!(recording.to_string().parse::<Recording>(), Ok(recording));
+        assert_eq!("input 1".parse::<Recording>(), Err("input 1".to_owned()));
+    }
+
+    #[test]
+    fn test_file_round_trip() {
+        let recording = record(&[3, 5, 0]);
+        let path = std::env::temp_dir().join(format!("intcode-recording-{}.txt", std::process::id()));
+        let filename = path.to_str().unwrap();
+        recording.save(filename).unwrap();
+        let loaded = Recording::load(filename);
+        fs::remove_file(filename).unwrap();
+        assert_eq!(loaded.unwrap(), recording);
+    }
+
+    #[test]
+    fn test_replay() {
+        let recording = record(&[3, 5, 0]);
+        let mut replay = Replay::new(doubler(), &recording, true);
+        assert_eq!(replay.run_to_end(), Ok(vec![6, 10]));
+        assert!(replay.is_finished());
+    }
+
+    #[test]
+    fn test_replay_output_mismatch() {
+        let mut recording = record(&[3, 0]);
+        recording.events[1] = Event::Output(7);
+        let mut replay = Replay::new(doubler(), &recording, true);
+        assert_eq!(replay.run(), Err(Error::OutputMismatch { index: 0, expected: Some(7), actual: 6 }));
+
+        let mut replay = Replay::new(doubler(), &recording, false);
+        assert_eq!(replay.run_to_end(), Ok(vec![6]));
+    }
+
+    #[test]
+    fn test_replay_missing_output() {
+        let mut recording = record(&[3, 0]);
+        recording.events.push(Event::Output(8));
+        let mut replay = Replay::new(doubler(), &recording, true);
+        assert_eq!(replay.run(), Ok(State::Output(6)));
+        assert_eq!(replay.run(), Err(Error::MissingOutput { index: 1, expected: 8 }));
+
+        let mut replay = Replay::new(doubler(), &recording, false);
+        assert_eq!(replay.run_to_end(), Ok(vec![6]));
+    }
+
+    #[test]
+    fn test_replay_unused_input() {
+        let mut recording = record(&[3, 0]);
+        recording.events.push(Event::Input { instruction: 20, value: 4 });
+        let mut replay = Replay::new(doubler(), &recording, true);
+        assert_eq!(replay.run(), Ok(State::Output(6)));
+        assert_eq!(replay.run(), Err(Error::UnusedInput { index: 2, expected_instruction: 20 }));
+
+        let mut replay = Replay::new(doubler(), &recording, false);
+        assert_eq!(replay.run_to_end(), Ok(vec![6]));
+        assert!(!replay.is_finished());
+    }
+
+    #[test]
+    fn test_record_queued_inputs() {
+        let mut machine = doubler();
+        machine.push_input(3);
+        let mut recorder = Recorder::new(machine);
+        recorder.push_input(0);
+        while let State::Output(_) = recorder.run().unwrap() {}
+        assert_eq!(recorder.into_recording(), record(&[3, 0]));
+    }
+
+    #[test]
+    fn test_replay_input_mismatch() {
+        let mut recording = record(&[3, 0]);
+        recording.events[2] = Event::Input { instruction: 4, value: 0 };
+        let mut replay = Replay::new(doubler(), &recording, false);
+        assert_eq!(replay.run(), Ok(State::Output(6)));
+        assert_eq!(replay.run(), Err(Error::InputMismatch { index: 1, expected_instruction: 4, actual_instruction: 5 }));
+    }
+
+    #[test]
+    fn test_continue_after_replay() {
+        let recording = record(&[3]);
+        let mut replay = Replay::new(doubler(), &recording, true);
+        assert_eq!(replay.run(), Ok(State::Output(6)));
+        assert_eq!(replay.run(), Ok(State::AwaitingInput));
+        assert!(replay.is_finished());
+        let mut machine = replay.into_machine();
+        assert_eq!(machine.run_with(&[4, 0]), Ok(vec![8]));
+    }
+}
@@ -0,0 +1,184 @@
+//! Searches for values to patch into a program, like the noun and verb of
+//! day 2.
+//!
+//! Every combination of the patched values is tried, in lexicographic order,
+//! and the value taken from the finished run is tested against a predicate.
+//! Runs which fail or execute more instructions than the budget never match.
+
+use std::ops::Range;
+
+use super::batch::Batch;
+use super::{Machine, State};
+
+/// Values to try at an address.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Patch {
+    pub address: usize,
+    pub values: Range<i64>,
+}
+
+/// What a run is judged by.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Extract {
+    /// A memory cell after the program halts, 0 past the end of memory.
+    Memory(usize),
+    /// The output at this position.
+    Output(usize),
+    /// The last output before the program halts.
+    LastOutput,
+}
+
+pub struct Search {
+    batch: Batch,
+    inputs: Vec<i64>,
+    patches: Vec<Patch>,
+    extract: Extract,
+    budget: u64,
+}
+
+/// Every combination of the patched values, the last patch varying fastest.
+struct Assignments<'a> {
+    patches: &'a [Patch],
+    next: Option<Vec<i64>>,
+}
+
+impl Iterator for Assignments<'_> {
+    type Item = Vec<i64>;
+
+    fn next(&mut self) -> Option<Vec<i64>> {
+        let current = self.next.take()?;
+        let mut next = current.clone();
+        for (index, patch) in self.patches.iter().enumerate().rev() {
+            next[index] += 1;
+            if next[index] < patch.values.end {
+                self.next = Some(next);
+                break;
+            }
+            next[index] = patch.values.start;
+        }
+        Some(current)
+    }
+}
+
+impl Search {
+    /// Every run may execute up to `budget` instructions. Fails if a patch
+    /// lies outside the program.
+    pub fn new(program: &[i64], patches: Vec<Patch>, extract: Extract, budget: u64) -> Result<Search, String> {
+        Search::with_inputs(program, &[], patches, extract, budget)
+    }
+
+    /// Every run also reads `inputs`.
+    pub fn with_inputs(
+        program: &[i64],
+        inputs: &[i64],
+        patches: Vec<Patch>,
+        extract: Extract,
+        budget: u64,
+    ) -> Result<Search, String> {
+        if let Some(patch) = patches.iter().find(|patch| patch.address >= program.len()) {
+            return Err(format!("patch address {} is outside the program of length {}", patch.address, program.len()));
+        }
+        Ok(Search { batch: Batch::new(program), inputs: inputs.to_vec(), patches, extract, budget })
+    }
+
+    fn assignments(&self) -> Assignments<'_> {
+        let empty = self.patches.iter().any(|patch| patch.values.is_empty());
+        let first = self.patches.iter().map(|patch| patch.values.start).collect();
+        Assignments { patches: &self.patches, next: if empty { None } else { Some(first) } }
+    }
+
+    /// Runs the program with `assignment` patched in, one value per patch.
+    pub fn evaluate(&self, assignment: &[i64]) -> Option<i64> {
+        self.run(self.batch.machine(), assignment)
+    }
+
+    fn run(&self, mut machine: Machine, assignment: &[i64]) -> Option<i64> {
+        let memory = machine.memory_mut();
+        for (patch, value) in self.patches.iter().zip(assignment) {
+            memory[patch.address] = *value;
+        }
+        machine.set_instruction_limit(self.budget);
+        for input in &self.inputs {
+            machine.push_input(*input);
+        }
+        let mut outputs = Vec::new();
+        loop {
+            match machine.run().ok()? {
+                State::Output(value) => outputs.push(value),
+                State::Halted => break,
+                State::AwaitingInput => return None,
+            }
+        }
+        match self.extract {
+            Extract::Memory(address) => Some(machine.memory().get(address).cloned().unwrap_or(0)),
+            Extract::Output(index) => outputs.get(index).cloned(),
+            Extract::LastOutput => outputs.last().cloned(),
+        }
+    }
+
+    /// The first assignment whose value satisfies `predicate`. The runs are
+    /// spread over all cores and stop once it is found.
+    pub fn find_first<P: Fn(i64) -> bool + Sync>(&self, predicate: P) -> Option<Vec<i64>> {
+        let job = |machine, assignment: &Vec<i64>| self.run(machine, assignment);
+        let found = self.batch.find(self.assignments(), job, |value| value.is_some_and(&predicate));
+        found.map(|(assignment, _)| assignment)
+    }
+
+    /// Every assignment whose value satisfies `predicate`, in order. The runs
+    /// are spread over all cores.
+    pub fn find_all<P: Fn(i64) -> bool + Sync>(&self, predicate: P) -> Vec<Vec<i64>> {
+        let job = |machine, assignment: &Vec<i64>| {
+            self.run(machine, assignment).filter(|value| predicate(*value)).map(|_| assignment.clone())
+        };
+        self.batch.map(self.assignments(), job).into_iter().flatten().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_all() {
+        // Adds the cells at the two patched addresses, 99 is at address 4.
+        let patches = vec![Patch { address: 1, values: 0..100 }, Patch { address: 2, values: 0..100 }];
+        let search = Search::new(&[1, 0, 0, 0, 99], patches, Extract::Memory(0), 10).unwrap();
+        let found = search.find_all(|value| value == 100);
+        assert_eq!(found, vec![vec![0, 4], vec![1, 4], vec![4, 0]]);
+        assert_eq!(search.find_first(|value| value == -1), None);
+        assert_eq!(search.find_first(|value| value == 100), Some(vec![0, 4]));
+    }
+
+    #[test]
+    fn test_patch_addresses() {
+        let patches = vec![Patch { address: 5, values: 0..10 }];
+        let error = Search::new(&[1, 0, 0, 0, 99], patches, Extract::Memory(0), 10).err();
+        assert_eq!(error, Some("patch address 5 is outside the program of length 5".to_owned()));
+        // Cells past the end of memory read as zero.
+        let patches = vec![Patch { address: 4, values: 99..100 }];
+        let search = Search::new(&[1, 0, 0, 0, 99], patches, Extract::Memory(9), 10).unwrap();
+        assert_eq!(search.evaluate(&[99]), Some(0));
+    }
+
+    #[test]
+    fn test_outputs() {
+        let program = crate::intcode![in x; mul x, y -> x; out x; out #7; halt; x: 0; y: 0];
+        let patches = vec![Patch { address: 12, values: -3..4 }];
+        let search = Search::with_inputs(&program, &[5], patches.clone(), Extract::Output(0), 10).unwrap();
+        assert_eq!(search.find_all(|value| value < 0), vec![vec![-3], vec![-2], vec![-1]]);
+        let search = Search::with_inputs(&program, &[5], patches.clone(), Extract::LastOutput, 10).unwrap();
+        assert_eq!(search.find_all(|value| value == 7).len(), 7);
+        // Without the input every run fails.
+        let search = Search::new(&program, patches, Extract::LastOutput, 10).unwrap();
+        assert_eq!(search.find_all(|_| true), Vec::<Vec<i64>>::new());
+    }
+
+    #[test]
+    fn test_budget() {
+        // Counts down from the patched value, one loop takes two instructions.
+        let program = crate::intcode![again: add n, #-1 -> n; jt n, #again; out #1; halt; n: 0];
+        let patches = vec![Patch { address: 10, values: 1..10 }];
+        let search = Search::new(&program, patches, Extract::LastOutput, 10).unwrap();
+        assert_eq!(search.find_all(|_| true), vec![vec![1], vec![2], vec![3], vec![4]]);
+    }
+}
@@ -0,0 +1,606 @@
+//! Symbolic evaluation of intcode programs.
+//!
+//! Selected memory cells and inputs are replaced by symbols and the program
+//! builds expression trees instead of numbers. Control flow has to stay
+//! concrete: a branch or a write whose target depends on a symbol stops the
+//! evaluation. Linear results can then be solved for directly instead of
+//! trying every possible assignment.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::error;
+use std::fmt;
+use std::ops::Range;
+
+use super::{instruction_to_op_code, Machine, Mode, OpCode};
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Symbol {
+    /// Initial value of a memory cell.
+    Memory(usize),
+    /// The n-th input read by the program.
+    Input(usize),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Expr {
+    Constant(i64),
+    Symbol(Symbol),
+    /// A value the evaluation could not track, e.g. a read through a symbolic address.
+    Unknown,
+    Add(Box<Expr>, Box<Expr>),
+    Multiply(Box<Expr>, Box<Expr>),
+    LessThan(Box<Expr>, Box<Expr>),
+    Equals(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// `None` when both sides are constants and their sum overflows.
+    fn add(left: Expr, right: Expr) -> Option<Expr> {
+        Some(match (left, right) {
+            (Expr::Constant(left), Expr::Constant(right)) => Expr::Constant(left.checked_add(right)?),
+            (Expr::Constant(0), other) | (other, Expr::Constant(0)) => other,
+            (left, right) => Expr::Add(Box::new(left), Box::new(right)),
+        })
+    }
+
+    /// `None` when both sides are constants and their product overflows.
+    fn multiply(left: Expr, right: Expr) -> Option<Expr> {
+        Some(match (left, right) {
+            (Expr::Constant(left), Expr::Constant(right)) => Expr::Constant(left.checked_mul(right)?),
+            (Expr::Constant(1), other) | (other, Expr::Constant(1)) => other,
+            (left, right) => Expr::Multiply(Box::new(left), Box::new(right)),
+        })
+    }
+
+    fn less_than(left: Expr, right: Expr) -> Expr {
+        match (left, right) {
+            (Expr::Constant(left), Expr::Constant(right)) => Expr::Constant(if left < right { 1 } else { 0 }),
+            (left, right) => Expr::LessThan(Box::new(left), Box::new(right)),
+        }
+    }
+
+    fn equals(left: Expr, right: Expr) -> Expr {
+        match (left, right) {
+            (Expr::Constant(left), Expr::Constant(right)) => Expr::Constant(if left == right { 1 } else { 0 }),
+            (left, right) => Expr::Equals(Box::new(left), Box::new(right)),
+        }
+    }
+
+    pub fn as_constant(&self) -> Option<i64> {
+        match self {
+            Expr::Constant(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Rewrites the expression as `constant + sum(coefficient * symbol)`.
+    ///
+    /// Returns `None` for comparisons, unknown values, products of symbols and
+    /// when a coefficient overflows.
+    pub fn linear(&self) -> Option<Linear> {
+        match self {
+            Expr::Constant(value) => Some(Linear { constant: *value, terms: BTreeMap::new() }),
+            Expr::Symbol(symbol) => {
+                let mut terms = BTreeMap::new();
+                terms.insert(*symbol, 1);
+                Some(Linear { constant: 0, terms })
+            }
+            Expr::Add(left, right) => {
+                let mut left = left.linear()?;
+                let right = right.linear()?;
+                left.constant = left.constant.checked_add(right.constant)?;
+                for (symbol, coefficient) in right.terms {
+                    let sum = left.terms.entry(symbol).or_insert(0);
+                    *sum = sum.checked_add(coefficient)?;
+                }
+                left.terms.retain(|_, coefficient| *coefficient != 0);
+                Some(left)
+            }
+            Expr::Multiply(left, right) => {
+                let left = left.linear()?;
+                let right = right.linear()?;
+                let (factor, mut linear) = if left.terms.is_empty() {
+                    (left.constant, right)
+                } else if right.terms.is_empty() {
+                    (right.constant, left)
+                } else {
+                    return None;
+                };
+                linear.constant = linear.constant.checked_mul(factor)?;
+                for coefficient in linear.terms.values_mut() {
+                    *coefficient = coefficient.checked_mul(factor)?;
+                }
+                linear.terms.retain(|_, coefficient| *coefficient != 0);
+                Some(linear)
+            }
+            Expr::Unknown | Expr::LessThan(_, _) | Expr::Equals(_, _) => None,
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Constant(value) => write!(f, "{}", value),
+            Expr::Symbol(Symbol::Memory(address)) => write!(f, "m{}", address),
+            Expr::Symbol(Symbol::Input(index)) => write!(f, "in{}", index),
+            Expr::Unknown => write!(f, "?"),
+            Expr::Add(left, right) => write!(f, "({} + {})", left, right),
+            Expr::Multiply(left, right) => write!(f, "({} * {})", left, right),
+            Expr::LessThan(left, right) => write!(f, "({} < {})", left, right),
+            Expr::Equals(left, right) => write!(f, "({} == {})", left, right),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Linear {
+    pub constant: i64,
+    pub terms: BTreeMap<Symbol, i64>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Error {
+    Machine(super::Error),
+    /// The instruction, an address or a branch condition depends on a symbol.
+    NotConcrete { ip: usize },
+    StepLimit,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Machine(error) => write!(f, "{}", error),
+            Error::NotConcrete { ip } => write!(f, "control flow depends on a symbol at {}", ip),
+            Error::StepLimit => write!(f, "step limit reached"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<super::Error> for Error {
+    fn from(error: super::Error) -> Error {
+        Error::Machine(error)
+    }
+}
+
+pub struct SymbolicMachine {
+    memory: Vec<Expr>,
+    ip: usize,
+    relative_base: i64,
+    memory_limit: usize,
+    inputs: VecDeque<Expr>,
+    input_count: usize,
+    outputs: Vec<Expr>,
+}
+
+impl SymbolicMachine {
+    pub fn new(program: &[i64]) -> SymbolicMachine {
+        SymbolicMachine {
+            memory: program.iter().map(|value| Expr::Constant(*value)).collect(),
+            ip: 0,
+            relative_base: 0,
+            memory_limit: super::MEMORY_LIMIT,
+            inputs: VecDeque::new(),
+            input_count: 0,
+            outputs: Vec::new(),
+        }
+    }
+
+    /// Like `Machine::set_memory_limit`.
+    pub fn set_memory_limit(&mut self, limit: usize) {
+        self.memory_limit = limit
+    }
+
+    pub fn memory(&self) -> &[Expr] {
+        &self.memory
+    }
+
+    pub fn outputs(&self) -> &[Expr] {
+        &self.outputs
+    }
+
+    /// Replaces the initial value of `address` with `Symbol::Memory(address)`,
+    /// failing if the program doesn't reach that far.
+    pub fn symbolize_memory(&mut self, address: usize) -> Result<(), Error> {
+        let ip = self.ip;
+        let cell = self.memory.get_mut(address).ok_or(super::Error::InvalidAddress { ip, address: address as i64 })?;
+        *cell = Expr::Symbol(Symbol::Memory(address));
+        Ok(())
+    }
+
+    pub fn push_input(&mut self, value: i64) {
+        self.inputs.push_back(Expr::Constant(value));
+        self.input_count += 1;
+    }
+
+    /// Queues an input which is read as `Symbol::Input(n)`.
+    pub fn push_symbolic_input(&mut self) {
+        self.inputs.push_back(Expr::Symbol(Symbol::Input(self.input_count)));
+        self.input_count += 1;
+    }
+
+    /// Evaluates the program until it halts or `max_steps` instructions ran.
+    pub fn run(&mut self, max_steps: usize) -> Result<(), Error> {
+        for _ in 0..max_steps {
+            let ip = self.ip;
+            let instruction = self.concrete(self.read(ip)?)?;
+            let operation_code = instruction_to_op_code(instruction)
+                .ok_or(super::Error::InvalidInstruction { ip, instruction })?;
+            match operation_code {
+                OpCode::Add(mode_1, mode_2, mode_3) => {
+                    let value = Expr::add(self.parameter(mode_1, 1)?, self.parameter(mode_2, 2)?)
+                        .ok_or(super::Error::Overflow { ip })?;
+                    self.write_parameter(mode_3, 3, value)?;
+                    self.ip += 4;
+                }
+                OpCode::Multiply(mode_1, mode_2, mode_3) => {
+                    let value = Expr::multiply(self.parameter(mode_1, 1)?, self.parameter(mode_2, 2)?)
+                        .ok_or(super::Error::Overflow { ip })?;
+                    self.write_parameter(mode_3, 3, value)?;
+                    self.ip += 4;
+                }
+                OpCode::LessThan(mode_1, mode_2, mode_3) => {
+                    let value = Expr::less_than(self.parameter(mode_1, 1)?, self.parameter(mode_2, 2)?);
+                    self.write_parameter(mode_3, 3, value)?;
+                    self.ip += 4;
+                }
+                OpCode::Equals(mode_1, mode_2, mode_3) => {
+                    let value = Expr::equals(self.parameter(mode_1, 1)?, self.parameter(mode_2, 2)?);
+                    self.write_parameter(mode_3, 3, value)?;
+                    self.ip += 4;
+                }
+                OpCode::JumpIfTrue(mode_1, mode_2) | OpCode::JumpIfFalse(mode_1, mode_2) => {
+                    let condition = self.parameter(mode_1, 1)?;
+                    let condition = self.concrete(condition)?;
+                    let jump_if = matches!(operation_code, OpCode::JumpIfTrue(_, _));
+                    if (condition != 0) == jump_if {
+                        let target = self.parameter(mode_2, 2)?;
+                        self.ip = self.address(self.concrete(target)?)?;
+                    } else {
+                        self.ip += 3;
+                    }
+                }
+                OpCode::Save(mode) => {
+                    let input = self.inputs.pop_front().ok_or(super::Error::EndOfInput { ip })?;
+                    self.write_parameter(mode, 1, input)?;
+                    self.ip += 2;
+                }
+                OpCode::Output(mode) => {
+                    let output = self.parameter(mode, 1)?;
+                    self.outputs.push(output);
+                    self.ip += 2;
+                }
+                OpCode::AdjustRelativeBase(mode) => {
+                    let offset = self.parameter(mode, 1)?;
+                    let offset = self.concrete(offset)?;
+                    self.relative_base = self.relative_base.checked_add(offset).ok_or(super::Error::Overflow { ip })?;
+                    self.ip += 2;
+                }
+                OpCode::Halt => return Ok(()),
+            }
+        }
+        Err(Error::StepLimit)
+    }
+
+    fn concrete(&self, value: Expr) -> Result<i64, Error> {
+        value.as_constant().ok_or(Error::NotConcrete { ip: self.ip })
+    }
+
+    fn offset_address(&self, base: i64, offset: i64) -> Result<usize, Error> {
+        let address = base.checked_add(offset).ok_or(super::Error::Overflow { ip: self.ip })?;
+        self.address(address)
+    }
+
+    fn address(&self, address: i64) -> Result<usize, Error> {
+        if address < 0 || address as u64 >= self.memory_limit as u64 {
+            return Err(super::Error::InvalidAddress { ip: self.ip, address }.into());
+        }
+        Ok(address as usize)
+    }
+
+    fn read(&self, address: usize) -> Result<Expr, Error> {
+        Ok(self.memory.get(address).cloned().unwrap_or(Expr::Constant(0)))
+    }
+
+    fn parameter(&self, mode: Mode, offset: usize) -> Result<Expr, Error> {
+        let value = self.read(self.ip + offset)?;
+        let base = match mode {
+            Mode::Immediate => return Ok(value),
+            Mode::Position => 0,
+            Mode::Relative => self.relative_base,
+        };
+        match value.as_constant() {
+            Some(address) => self.read(self.offset_address(base, address)?),
+            None => Ok(Expr::Unknown),
+        }
+    }
+
+    fn write_parameter(&mut self, mode: Mode, offset: usize, value: Expr) -> Result<(), Error> {
+        let destination = self.read(self.ip + offset)?;
+        let base = if mode == Mode::Relative { self.relative_base } else { 0 };
+        let destination = self.offset_address(base, self.concrete(destination)?)?;
+        if destination >= self.memory.len() {
+            self.memory.resize(destination + 1, Expr::Constant(0));
+        }
+        self.memory[destination] = value;
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Solution {
+    /// Values for the symbols, in the order of the domain.
+    Found(Vec<i64>),
+    NoSolution,
+    /// The expression is not linear in the domain symbols.
+    NonLinear,
+}
+
+/// Solves `expr == target` with every symbol taking a value from its range.
+///
+/// All symbols with a non-zero coefficient but the last one are enumerated,
+/// the last one is computed, so the first solution in lexicographic order is
+/// returned. Symbols with a zero coefficient keep the start of their range.
+/// Assignments on which the sum overflows are skipped.
+pub fn solve(expr: &Expr, target: i64, domain: &[(Symbol, Range<i64>)]) -> Solution {
+    let linear = match expr.linear() {
+        Some(linear) => linear,
+        None => return Solution::NonLinear,
+    };
+    if linear.terms.keys().any(|symbol| domain.iter().all(|(known, _)| known != symbol)) {
+        return Solution::NonLinear;
+    }
+    let coefficients: Vec<i64> = domain
+        .iter()
+        .map(|(symbol, _)| linear.terms.get(symbol).cloned().unwrap_or(0))
+        .collect();
+    if domain.iter().any(|(_, range)| range.start >= range.end) {
+        return Solution::NoSolution;
+    }
+    let last = match coefficients.iter().rposition(|coefficient| *coefficient != 0) {
+        Some(last) => last,
+        None if linear.constant == target => {
+            return Solution::Found(domain.iter().map(|(_, range)| range.start).collect())
+        }
+        None => return Solution::NoSolution,
+    };
+    let free: Vec<usize> = (0..last).filter(|index| coefficients[*index] != 0).collect();
+    let mut values: Vec<i64> = domain.iter().map(|(_, range)| range.start).collect();
+    loop {
+        let rest = target.checked_sub(linear.constant).and_then(|rest| {
+            free.iter().try_fold(rest, |rest, index| rest.checked_sub(coefficients[*index].checked_mul(values[*index])?))
+        });
+        if let Some(rest) = rest {
+            if rest.checked_rem(coefficients[last]) == Some(0) {
+                let value = rest / coefficients[last];
+                if domain[last].1.contains(&value) {
+                    values[last] = value;
+                    return Solution::Found(values);
+                }
+            }
+        }
+        if !next_assignment(&mut values, &free, domain) {
+            return Solution::NoSolution;
+        }
+    }
+}
+
+/// Advances `values` at the `positions` like an odometer, the last position fastest.
+fn next_assignment(values: &mut [i64], positions: &[usize], domain: &[(Symbol, Range<i64>)]) -> bool {
+    for index in positions.iter().rev() {
+        values[*index] += 1;
+        if values[*index] < domain[*index].1.end {
+            return true;
+        }
+        values[*index] = domain[*index].1.start;
+    }
+    false
+}
+
+/// Finds values for the `patches` cells which leave `target` in memory `cell`
+/// after the program halts within `budget` instructions.
+///
+/// The program is evaluated symbolically first and the linear relation is
+/// solved directly, a solution is then confirmed on a concrete machine. When
+/// evaluation fails, the result is not linear or the solution doesn't hold up,
+/// every assignment is tried on a concrete machine instead. Fails if a patched
+/// cell lies outside the program.
+pub fn solve_memory(
+    program: &[i64],
+    patches: &[(usize, Range<i64>)],
+    cell: usize,
+    target: i64,
+    budget: u64,
+) -> Result<Option<Vec<i64>>, Error> {
+    let mut machine = SymbolicMachine::new(program);
+    for (address, _) in patches {
+        machine.symbolize_memory(*address)?;
+    }
+    let domain: Vec<(Symbol, Range<i64>)> = patches
+        .iter()
+        .map(|(address, range)| (Symbol::Memory(*address), range.clone()))
+        .collect();
+    if machine.run(budget as usize).is_ok() {
+        let value = machine.memory().get(cell).cloned().unwrap_or(Expr::Constant(0));
+        match solve(&value, target, &domain) {
+            Solution::Found(values) if check(program, patches, &values, cell, target, budget) => {
+                return Ok(Some(values))
+            }
+            Solution::NoSolution => return Ok(None),
+            Solution::Found(_) | Solution::NonLinear => (),
+        }
+    }
+    Ok(brute_force(program, patches, cell, target, budget))
+}
+
+/// Whether the program with `values` patched in leaves `target` in `cell`.
+fn check(
+    program: &[i64],
+    patches: &[(usize, Range<i64>)],
+    values: &[i64],
+    cell: usize,
+    target: i64,
+    budget: u64,
+) -> bool {
+    let mut machine = Machine::new(program);
+    machine.set_instruction_limit(budget);
+    for ((address, _), value) in patches.iter().zip(values) {
+        match machine.memory_mut().get_mut(*address) {
+            Some(slot) => *slot = *value,
+            None => return false,
+        }
+    }
+    machine.run_with(&[]).is_ok() && machine.memory().get(cell).cloned().unwrap_or(0) == target
+}
+
+fn brute_force(
+    program: &[i64],
+    patches: &[(usize, Range<i64>)],
+    cell: usize,
+    target: i64,
+    budget: u64,
+) -> Option<Vec<i64>> {
+    let positions: Vec<usize> = (0..patches.len()).collect();
+    let domain: Vec<(Symbol, Range<i64>)> = patches
+        .iter()
+        .map(|(address, range)| (Symbol::Memory(*address), range.clone()))
+        .collect();
+    if patches.iter().any(|(_, range)| range.start >= range.end) {
+        return None;
+    }
+    if patches.iter().any(|(address, _)| *address >= program.len()) {
+        return None;
+    }
+    let mut values: Vec<i64> = patches.iter().map(|(_, range)| range.start).collect();
+    loop {
+        if check(program, patches, &values, cell, target, budget) {
+            return Some(values);
+        }
+        if !next_assignment(&mut values, &positions, &domain) {
+            return None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::intcode;
+
+    fn noun_verb() -> Vec<(Symbol, Range<i64>)> {
+        vec![(Symbol::Memory(1), 0..100), (Symbol::Memory(2), 0..100)]
+    }
+
+    #[test]
+    fn test_symbolic_memory() {
+        let mut machine = SymbolicMachine::new(&[1, 0, 0, 0, 2, 0, 9, 0, 99, 7]);
+        machine.symbolize_memory(9).unwrap();
+        machine.run(10).unwrap();
+        assert_eq!(machine.memory()[0].to_string(), "(2 * m9)");
+        let linear = machine.memory()[0].linear().unwrap();
+        assert_eq!(linear.constant, 0);
+        assert_eq!(linear.terms.get(&Symbol::Memory(9)), Some(&2));
+    }
+
+    #[test]
+    fn test_symbolic_input() {
+        let mut machine = SymbolicMachine::new(&[3, 9, 1002, 9, 3, 9, 4, 9, 99, 0]);
+        machine.push_symbolic_input();
+        machine.run(10).unwrap();
+        assert_eq!(machine.outputs(), &[Expr::Multiply(
+            Box::new(Expr::Symbol(Symbol::Input(0))),
+            Box::new(Expr::Constant(3)),
+        )]);
+        assert_eq!(solve(&machine.outputs()[0], 21, &[(Symbol::Input(0), -100..100)]), Solution::Found(vec![7]));
+        assert_eq!(solve(&machine.outputs()[0], 22, &[(Symbol::Input(0), -100..100)]), Solution::NoSolution);
+    }
+
+    #[test]
+    fn test_symbolic_branch() {
+        let mut machine = SymbolicMachine::new(&[3, 7, 1005, 7, 6, 99, 99, 0]);
+        machine.push_symbolic_input();
+        assert_eq!(machine.run(10), Err(Error::NotConcrete { ip: 2 }));
+    }
+
+    #[test]
+    fn test_non_linear() {
+        let product = Expr::multiply(Expr::Symbol(Symbol::Memory(1)), Expr::Symbol(Symbol::Memory(2))).unwrap();
+        assert_eq!(solve(&product, 12, &noun_verb()), Solution::NonLinear);
+        let program = vec![2, 7, 8, 5, 99, 0, 0, 3, 4];
+        assert_eq!(solve_memory(&program, &[(7, 0..10), (8, 0..10)], 5, 12, 100), Ok(Some(vec![2, 6])));
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut machine = SymbolicMachine::new(&[1102, i64::MAX, 2, 0, 99]);
+        assert_eq!(machine.run(10), Err(Error::Machine(super::super::Error::Overflow { ip: 0 })));
+        let mut machine = SymbolicMachine::new(&[109, i64::MAX, 109, 1, 99]);
+        assert_eq!(machine.run(10), Err(Error::Machine(super::super::Error::Overflow { ip: 2 })));
+
+        // 2^62 * m1, doubled, no longer fits.
+        let huge = Expr::multiply(Expr::Symbol(Symbol::Memory(1)), Expr::Constant(1 << 62)).unwrap();
+        assert_eq!(huge.linear().map(|linear| linear.terms[&Symbol::Memory(1)]), Some(1 << 62));
+        let doubled = Expr::add(huge.clone(), huge.clone()).unwrap();
+        assert_eq!(doubled.linear(), None);
+        assert_eq!(solve(&huge, 1, &noun_verb()), Solution::NoSolution);
+        assert_eq!(solve(&huge, i64::MIN, &[(Symbol::Memory(1), -5..5)]), Solution::Found(vec![-2]));
+    }
+
+    #[test]
+    fn test_zero_coefficients() {
+        // m1 - m1 + 3 * m2 with m1 free over a huge range.
+        let expr = Expr::add(
+            Expr::add(Expr::Symbol(Symbol::Memory(1)), Expr::multiply(Expr::Symbol(Symbol::Memory(1)), Expr::Constant(-1)).unwrap())
+                .unwrap(),
+            Expr::multiply(Expr::Symbol(Symbol::Memory(2)), Expr::Constant(3)).unwrap(),
+        )
+        .unwrap();
+        let domain = vec![(Symbol::Memory(1), 0..i64::MAX), (Symbol::Memory(2), 0..i64::MAX), (Symbol::Memory(3), 5..9)];
+        assert_eq!(solve(&expr, 9, &domain), Solution::Found(vec![0, 3, 5]));
+        assert_eq!(solve(&expr, 10, &domain[..2]), Solution::NoSolution);
+    }
+
+    #[test]
+    fn test_empty_range() {
+        let constant = Expr::Constant(3);
+        assert_eq!(solve(&constant, 3, &[(Symbol::Memory(1), 3..3)]), Solution::NoSolution);
+        assert_eq!(solve(&constant, 3, &[(Symbol::Memory(1), 3..4)]), Solution::Found(vec![3]));
+    }
+
+    #[test]
+    fn test_budget() {
+        // Stores m8 in cell 0, then loops forever unless m9 is 0.
+        let program = vec![1001, 8, 0, 0, 1005, 9, 4, 99, 0, 0];
+        assert_eq!(solve_memory(&program, &[(8, 0..10), (9, 0..3)], 0, 7, 100), Ok(Some(vec![7, 0])));
+        assert_eq!(brute_force(&program, &[(8, 0..10), (9, 1..3)], 0, 7, 100), None);
+        assert_eq!(solve_memory(&program, &[(8, 0..10), (9, 1..3)], 0, 7, 100), Ok(None));
+    }
+
+    #[test]
+    fn test_out_of_range_patch() {
+        let program = vec![1, 0, 0, 0, 99];
+        let error = Error::Machine(super::super::Error::InvalidAddress { ip: 0, address: 5 });
+        assert_eq!(solve_memory(&program, &[(5, 0..10)], 0, 2, 100), Err(error));
+        assert_eq!(brute_force(&program, &[(5, 0..10)], 0, 2, 100), None);
+        // Cells past the end read as zero.
+        assert_eq!(solve_memory(&program, &[(1, 0..10)], 7, 0, 100), Ok(Some(vec![0])));
+    }
+
+    #[test]
+    fn test_day_2() {
+        let program = intcode::read_input("input/day2.txt");
+        let mut machine = SymbolicMachine::new(&program);
+        machine.symbolize_memory(1).unwrap();
+        machine.symbolize_memory(2).unwrap();
+        machine.run(1_000).unwrap();
+        assert!(machine.memory()[0].linear().is_some());
+
+        let answer = solve_memory(&program, &[(1, 0..100), (2, 0..100)], 0, 19_690_720, 1_000).unwrap();
+        let expected = brute_force(&program, &[(1, 0..100), (2, 0..100)], 0, 19_690_720, 1_000);
+        assert!(answer.is_some());
+        assert_eq!(answer, expected);
+    }
+}
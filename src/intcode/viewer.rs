@@ -0,0 +1,145 @@
+//! Hexdump-style view of machine memory.
+//!
+//! Every row starts with the address of its first cell. Each cell has a
+//! marker: `>` for the ip, `@` for the cell the relative base points at and
+//! `|` for the start of an instruction found by CFG recovery. A cell both the
+//! ip and the relative base point at is shown as `>@`.
+//!
+//! ```text
+//! ip 6, rb 7
+//!      0: |109    7 |  3    9 |  4
+//!      5:    9 > 99 @  0    0   42
+//! ```
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::ops::Range;
+
+use super::cfg::Cfg;
+use super::Machine;
+
+pub struct View<'a> {
+    machine: &'a Machine,
+    width: usize,
+    rows: Range<usize>,
+    instructions: bool,
+}
+
+impl<'a> View<'a> {
+    /// All of memory, `width` cells per row; a width of zero is taken as one.
+    pub fn new(machine: &'a Machine, width: usize) -> View<'a> {
+        let width = width.max(1);
+        let rows = machine.memory().len().div_ceil(width);
+        View { machine, width, rows: 0..rows, instructions: true }
+    }
+
+    /// The row holding `address` and up to `radius` rows on either side.
+    pub fn around(machine: &'a Machine, width: usize, address: usize, radius: usize) -> View<'a> {
+        let View { width, rows: all, .. } = View::new(machine, width);
+        let row = address / width;
+        let rows = row.saturating_sub(radius)..(row + radius + 1).min(all.end.max(row + 1));
+        View { machine, width, rows, instructions: true }
+    }
+
+    /// Leaves out the instruction markers, which need a CFG recovery of the
+    /// whole memory.
+    pub fn without_instructions(self) -> View<'a> {
+        View { instructions: false, ..self }
+    }
+
+    fn instructions(&self) -> BTreeSet<usize> {
+        if !self.instructions {
+            return BTreeSet::new();
+        }
+        let cfg = Cfg::recover(self.machine.memory());
+        let mut starts: BTreeSet<usize> = cfg
+            .blocks
+            .values()
+            .flat_map(|block| block.instructions.iter().map(|instruction| instruction.address))
+            .collect();
+        starts.insert(self.machine.ip());
+        starts
+    }
+}
+
+impl fmt::Display for View<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let memory = self.machine.memory();
+        let cells = self.rows.start * self.width..(self.rows.end * self.width).min(memory.len());
+        let column = memory.get(cells).unwrap_or(&[]).iter().map(|value| value.to_string().len()).max().unwrap_or(1);
+        let instructions = self.instructions();
+        let relative_base = self.machine.relative_base();
+
+        writeln!(f, "ip {}, rb {}", self.machine.ip(), relative_base)?;
+        for row in self.rows.clone() {
+            let start = row * self.width;
+            write!(f, "{:>6}:", start)?;
+            for (address, value) in memory.iter().enumerate().skip(start).take(self.width) {
+                let ip = address == self.machine.ip();
+                let marker = if address as i64 == relative_base {
+                    if ip { ">@" } else { " @" }
+                } else if ip {
+                    " >"
+                } else if instructions.contains(&address) {
+                    " |"
+                } else {
+                    "  "
+                };
+                write!(f, "{}{:>width$}", marker, value, width = column)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::State;
+    use super::*;
+
+    #[test]
+    fn test_view() {
+        let mut machine = Machine::new(&[109, 7, 3, 9, 4, 9, 99, 0, 0, 0]);
+        machine.push_input(42);
+        assert_eq!(machine.run(), Ok(State::Output(42)));
+        assert_eq!(View::new(&machine, 5).to_string(), "\
+ip 6, rb 7
+     0: |109    7 |  3    9 |  4
+     5:    9 > 99 @  0    0   42
+");
+    }
+
+    #[test]
+    fn test_around() {
+        let machine = Machine::new(&(0..100).collect::<Vec<i64>>());
+        let view = View::around(&machine, 10, 55, 1).to_string();
+        let addresses: Vec<&str> = view.lines().skip(1).map(|line| line.split(':').next().unwrap().trim()).collect();
+        assert_eq!(addresses, vec!["40", "50", "60"]);
+        assert_eq!(View::around(&machine, 10, 5, 1).to_string().lines().count(), 3);
+        assert_eq!(View::around(&machine, 0, 5, 1).to_string().lines().count(), 4);
+    }
+
+    #[test]
+    fn test_zero_width() {
+        let machine = Machine::new(&[104, 1, 99]);
+        assert_eq!(View::new(&machine, 0).to_string(), "ip 0, rb 0\n     0:>@104\n     1:    1\n     2: | 99\n");
+    }
+
+    #[test]
+    fn test_shared_cell() {
+        let mut machine = Machine::new(&[109, 4, 104, 1, 99]);
+        assert_eq!(machine.run(), Ok(State::Output(1)));
+        assert_eq!(View::new(&machine, 5).to_string(), "ip 4, rb 4\n     0: |109    4 |104    1>@ 99\n");
+    }
+
+    #[test]
+    fn test_debug() {
+        let machine = Machine::new(&[104, 1, 99]);
+        assert_eq!(
+            format!("{:?}", machine),
+            "Machine { ip: 0, relative_base: 0, halted: false, instruction_count: 0, inputs: [] }"
+        );
+        assert!(format!("{:#?}", machine).ends_with("     0:>@104    1   99\n"));
+    }
+}
@@ -0,0 +1,8 @@
+pub mod intcode;
+//pub mod day1;
+pub mod day2;
+//pub mod day3;
+//pub mod day4;
+pub mod day5;
+pub mod day6;
+pub mod day7;
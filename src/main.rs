@@ -1,27 +1,19 @@
-extern crate permutohedron;
-
-mod intcode;
-//mod day1;
-//mod day2;
-//mod day3;
-//mod day4;
-mod day5;
-mod day6;
-mod day7;
+use advent_of_code_2019::{day2, day5, day6, day7};
 
 
 fn main() {
 //    println!("Day 1:");
 //    let answer_1 = day1::solve();
 //    println!("{}", answer_1);
-//
-//    println!("===============================================");
-//    println!("Day 2:");
-//    let answer_2 = day2::solve();
-//    match answer_2 {
-//        Some(actual_answer_2) => println!("{}", actual_answer_2),
-//        None => println!("Cannot find answer for day 2"),
-//    }
+
+    println!("===============================================");
+    println!("Day 2:");
+    let (answer_2_1, answer_2_2) = day2::solve();
+    println!("{}", answer_2_1);
+    match answer_2_2 {
+        Some(actual_answer_2) => println!("{}", actual_answer_2),
+        None => println!("Cannot find answer for day 2"),
+    }
 
 //        println!("===============================================");
 //        println!("Day 3:");
@@ -35,8 +27,9 @@ fn main() {
 
     println!("===============================================");
     println!("Day 5:");
-    let answer_5 = day5::solve();
-    println!("{}", answer_5);
+    let (answer_5_1, answer_5_2) = day5::solve();
+    println!("{}", answer_5_1);
+    println!("{}", answer_5_2);
 
     println!("===============================================");
     println!("Day 6:");
@@ -45,6 +38,7 @@ fn main() {
 
     println!("===============================================");
     println!("Day 7:");
-    let answer_7 = day7::solve();
-    println!("{}", answer_7);
+    let (answer_7_1, answer_7_2) = day7::solve();
+    println!("{}", answer_7_1);
+    println!("{}", answer_7_2);
 }